@@ -1,8 +1,23 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use ratatui::widgets::{ScrollbarState, TableState};
-use crate::server::Server;
-use crate::util::{used_as_percentage};
+use schema::{Cgroup, DiskIo, HistorySample, NetworkInterface, Process, ProcessSort, Resources};
+use crate::endpoint_file::EndpointFile;
+use crate::server::{EndpointOptions, Server, ServerId};
+use crate::theme::{Palette, Theme};
+use crate::util::{format_endpoint, parse_endpoint, used_as_percentage, Units};
+
+/// How the dashboard looks, set in the config file
+#[derive(Clone, Copy, Debug)]
+pub struct Appearance {
+    pub theme: Theme,
+    pub units: Units,
+    /// How far back the charts go
+    pub chart_window: Duration,
+}
 
 pub struct App {
     pub title: String,
@@ -11,22 +26,97 @@ pub struct App {
     pub should_quit: bool,
     pub servers: Vec<Server>,
     pub scroll: ScrollState,
-    pub cpu_chart_data: HashMap<usize, Vec<u64>>,
-    pub ram_chart_data: HashMap<usize, Vec<u64>>,
+    pub cpu_chart_data: HashMap<ServerId, Vec<u64>>,
+    pub ram_chart_data: HashMap<ServerId, Vec<RamSample>>,
     pub max_chart_data_points: usize,
     pub cpu_table: CpuTable,
-    pub previous_transmitted_total: HashMap<usize, u64>,
-    pub previous_received_total: HashMap<usize, u64>,
-    pub received_chart_data: HashMap<usize, Vec<f64>>,
-    pub transmitted_chart_data: HashMap<usize, Vec<f64>>,
+    pub previous_transmitted_total: HashMap<ServerId, u64>,
+    pub previous_received_total: HashMap<ServerId, u64>,
+    /// When the server answered with the previous totals, rates are taken over the time between two answers
+    pub previous_success: HashMap<ServerId, Instant>,
+    pub received_chart_data: HashMap<ServerId, Vec<f64>>,
+    pub transmitted_chart_data: HashMap<ServerId, Vec<f64>>,
+    pub previous_disk_read_total: HashMap<ServerId, u64>,
+    pub previous_disk_written_total: HashMap<ServerId, u64>,
+    /// In bytes per second
+    pub disk_read_chart_data: HashMap<ServerId, Vec<f64>>,
+    pub disk_written_chart_data: HashMap<ServerId, Vec<f64>>,
+    /// Bytes swapped in and out since boot
+    pub previous_swapped: HashMap<ServerId, (u64, u64)>,
+    /// Bytes swapped in and out per second
+    pub swap_rates: HashMap<ServerId, (f64, f64)>,
     pub last_update_time: Instant,
     pub update_interval: u64,
     pub show_endpoint_popup: bool,
     pub endpoint_input: InputState,
+    pub detail_view: DetailView,
+    pub process_table: ProcessTable,
+    pub process_sort: Arc<RwLock<ProcessSort>>,
+    pub cgroup_table: CpuTable,
+    pub cgroup_sort: CgroupSort,
+    pub network_filter: NetworkFilter,
+    pub disk_filter: DiskFilter,
+    pub backfilled: HashSet<ServerId>,
+    /// Set while we wait for the user to confirm removing the selected server
+    pub confirm_removal: bool,
+    /// Changes to the servers made in the UI that haven't been applied to the shared servers yet
+    pub server_edits: Vec<ServerEdit>,
+    /// Where the servers are saved to, None if there's nowhere to save them
+    pub endpoint_file: Option<EndpointFile>,
+    /// The outcome of the last save, shown until the next key press
+    pub status_message: Option<String>,
+    pub palette: Palette,
+    pub units: Units,
+    pub chart_window: Duration,
+}
+
+/// The pages available in the detailed view of a server
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DetailView {
+    Resources,
+    Processes,
+    Cgroups,
+}
+
+/// A change to the list of servers made in the UI. Servers are referred to by their id, so an edit
+/// applies to the right server even if the list changed before it was applied
+#[derive(Clone, Debug)]
+pub enum ServerEdit {
+    Add(Server),
+    Remove(ServerId),
+    /// Replaces the endpoint and options of a server. If the endpoint stays the same the server
+    /// keeps its id and the data fetched so far, otherwise it is a new server
+    Replace(ServerId, Server),
+    Swap(ServerId, ServerId),
+}
+
+impl ServerEdit {
+    pub fn apply(&self, servers: &mut Vec<Server>) {
+        let position = |servers: &Vec<Server>, id: &ServerId| servers.iter().position(|server| server.id == *id);
+        match self {
+            ServerEdit::Add(server) => servers.push(server.clone()),
+            ServerEdit::Remove(id) => servers.retain(|server| server.id != *id),
+            ServerEdit::Replace(id, server) => {
+                if let Some(old) = servers.iter_mut().find(|old| old.id == *id) {
+                    if old.endpoint == server.endpoint {
+                        old.options = server.options.clone();
+                    } else {
+                        *old = server.clone();
+                    }
+                }
+            }
+            ServerEdit::Swap(a, b) => {
+                if let (Some(a), Some(b)) = (position(servers, a), position(servers, b)) {
+                    servers.swap(a, b);
+                }
+            }
+        }
+    }
 }
 
 impl App {
-    pub fn new(title: String, tick_rate: u64, update_interval: u64) -> App {
+    pub fn new(title: String, tick_rate: u64, update_interval: u64, process_sort: Arc<RwLock<ProcessSort>>,
+               appearance: Appearance) -> App {
         App {
             title,
             tick_rate,
@@ -36,16 +126,38 @@ impl App {
             scroll: ScrollState::new(),
             cpu_chart_data: HashMap::new(),
             ram_chart_data: HashMap::new(),
-            max_chart_data_points: (60 * 1000 / tick_rate) as usize,
+            max_chart_data_points: (appearance.chart_window.as_millis() as u64 / tick_rate).max(2) as usize,
             cpu_table: CpuTable::new(),
             previous_transmitted_total: HashMap::new(),
             previous_received_total: HashMap::new(),
+            previous_success: HashMap::new(),
             received_chart_data: HashMap::new(),
             transmitted_chart_data:HashMap::new(),
+            previous_disk_read_total: HashMap::new(),
+            previous_disk_written_total: HashMap::new(),
+            disk_read_chart_data: HashMap::new(),
+            disk_written_chart_data: HashMap::new(),
+            previous_swapped: HashMap::new(),
+            swap_rates: HashMap::new(),
             last_update_time: Instant::now(),
             update_interval,
             show_endpoint_popup: false,
             endpoint_input: InputState::default(),
+            detail_view: DetailView::Resources,
+            process_table: ProcessTable::new(),
+            process_sort,
+            cgroup_table: CpuTable::new(),
+            cgroup_sort: CgroupSort::default(),
+            network_filter: NetworkFilter::All,
+            disk_filter: DiskFilter::All,
+            backfilled: HashSet::new(),
+            confirm_removal: false,
+            server_edits: vec![],
+            endpoint_file: None,
+            status_message: None,
+            palette: appearance.theme.palette(),
+            units: appearance.units,
+            chart_window: appearance.chart_window,
         }
     }
 
@@ -53,25 +165,75 @@ impl App {
         if c == 'q' {
             self.should_quit = true;
         }
+        if c == 'w' {
+            self.status_message = Some(match self.save_endpoints() {
+                Ok(path) => format!("Saved {} servers to {}", self.servers.len(), path),
+                Err(err) => format!("Failed to save the servers: {}", err),
+            });
+        }
         if c == 'p' {
-            self.show_endpoint_popup = !self.show_endpoint_popup;
+            self.endpoint_input.start(None, String::new());
+            self.show_endpoint_popup = true;
+        }
+        if c == 'e' && self.tabs.index == 0 {
+            if let Some(server) = self.servers.get(self.scroll.selected as usize) {
+                self.endpoint_input.start_editing(server);
+                self.show_endpoint_popup = true;
+            }
+        }
+        if c == 'd' && self.tabs.index == 0 && !self.servers.is_empty() {
+            self.confirm_removal = true;
+        }
+        if c == 's' && self.showing_processes() {
+            let next_sort = next_process_sort(self.process_sort());
+            *self.process_sort.write().unwrap() = next_sort;
+            self.sort_processes();
+        }
+        if c == 's' && self.showing_cgroups() {
+            self.cgroup_sort = self.cgroup_sort.next();
+            self.cgroup_table.state.select(Some(0));
+        }
+        if c == 'i' && self.showing_resources() {
+            if let Some(server) = self.servers.get(self.tabs.index - 1) {
+                self.network_filter = self.network_filter.next(server);
+                self.reset_network_chart_data();
+            }
+        }
+        if c == 'o' && self.showing_resources() {
+            if let Some(server) = self.servers.get(self.tabs.index - 1) {
+                self.disk_filter = self.disk_filter.next(server);
+                self.reset_disk_chart_data();
+            }
         }
     }
 
     pub fn on_left(&mut self){
         self.tabs.previous();
         self.cpu_table.state.select(Some(0));
+        self.cgroup_table.state.select(Some(0));
+        self.process_table.reset();
+        self.sort_processes();
     }
 
     pub fn on_right(&mut self){
         self.tabs.next();
         self.cpu_table.state.select(Some(0));
+        self.cgroup_table.state.select(Some(0));
+        self.process_table.reset();
+        self.sort_processes();
     }
 
     pub fn on_up(&mut self) {
         if self.tabs.index == 0 {
             self.scroll.up()
         }
+        else if self.showing_processes() {
+            self.process_table.table.previous();
+            self.remember_selected_process();
+        }
+        else if self.showing_cgroups() {
+            self.cgroup_table.previous();
+        }
         else {
             self.cpu_table.previous();
         }
@@ -81,110 +243,653 @@ impl App {
         if self.tabs.index == 0 {
             self.scroll.down()
         }
+        else if self.showing_processes() {
+            self.process_table.table.next();
+            self.remember_selected_process();
+        }
+        else if self.showing_cgroups() {
+            self.cgroup_table.next();
+        }
         else {
             self.cpu_table.next();
         }
     }
 
+    /// Moves the selected server in the overview one step up or down
+    pub fn move_selected_server(&mut self, up: bool) {
+        if self.tabs.index != 0 {
+            return;
+        }
+        let selected = self.scroll.selected as usize;
+        let target = if up {
+            selected.checked_sub(1)
+        } else {
+            Some(selected + 1).filter(|target| *target < self.servers.len())
+        };
+        if let Some(target) = target {
+            self.edit_servers(ServerEdit::Swap(self.servers[selected].id, self.servers[target].id));
+            if up {
+                self.scroll.up();
+            } else {
+                self.scroll.down();
+            }
+        }
+    }
+
+    /// Removes the selected server if the user answered yes
+    pub fn on_confirm_removal(&mut self, confirmed: bool) {
+        self.confirm_removal = false;
+        if let Some(server) = self.servers.get(self.scroll.selected as usize).filter(|_| confirmed) {
+            self.edit_servers(ServerEdit::Remove(server.id));
+        }
+    }
+
+    /// Adds the server typed into the popup, or replaces the one being edited
+    pub fn on_enter(&mut self) {
+        if let Some(server) = self.endpoint_input.take_server() {
+            let edit = match self.endpoint_input.editing {
+                Some(id) => ServerEdit::Replace(id, server),
+                None => ServerEdit::Add(server),
+            };
+            self.edit_servers(edit);
+            self.show_endpoint_popup = false;
+        }
+    }
+
+    /// Writes the servers to the endpoint file and returns its path
+    pub fn save_endpoints(&mut self) -> Result<String, String> {
+        let file = self.endpoint_file.as_mut()
+            .ok_or_else(|| String::from("no endpoint file was loaded, choose one with --save-to"))?;
+        file.save(&self.servers).map_err(|err| format!("{}: {}", file.path.display(), err))?;
+        Ok(file.path.display().to_string())
+    }
+
+    /// Applies the edit to our copy of the servers right away and queues it for the shared servers
+    fn edit_servers(&mut self, edit: ServerEdit) {
+        if let (ServerEdit::Replace(id, server), Some(file)) = (&edit, self.endpoint_file.as_mut()) {
+            file.replaced(*id, server.id);
+        }
+        edit.apply(&mut self.servers);
+        self.forget_removed_servers();
+        self.tabs.update_tabs(&self.servers);
+        self.scroll.clamp(self.servers.len() as u16);
+        self.server_edits.push(edit);
+    }
+
+    /// Drops the state kept for servers that are no longer in the list
+    fn forget_removed_servers(&mut self) {
+        let ids: HashSet<ServerId> = self.servers.iter().map(|server| server.id).collect();
+        self.cpu_chart_data.retain(|id, _| ids.contains(id));
+        self.ram_chart_data.retain(|id, _| ids.contains(id));
+        self.previous_transmitted_total.retain(|id, _| ids.contains(id));
+        self.previous_received_total.retain(|id, _| ids.contains(id));
+        self.previous_success.retain(|id, _| ids.contains(id));
+        self.received_chart_data.retain(|id, _| ids.contains(id));
+        self.transmitted_chart_data.retain(|id, _| ids.contains(id));
+        self.previous_disk_read_total.retain(|id, _| ids.contains(id));
+        self.previous_disk_written_total.retain(|id, _| ids.contains(id));
+        self.disk_read_chart_data.retain(|id, _| ids.contains(id));
+        self.disk_written_chart_data.retain(|id, _| ids.contains(id));
+        self.previous_swapped.retain(|id, _| ids.contains(id));
+        self.swap_rates.retain(|id, _| ids.contains(id));
+        self.backfilled.retain(|id| ids.contains(id));
+    }
+
+    /// Switches between the pages of the detailed view
+    pub fn on_tab(&mut self) {
+        if self.tabs.index == 0 {
+            return;
+        }
+        self.detail_view = match self.detail_view {
+            DetailView::Resources => DetailView::Processes,
+            DetailView::Processes => DetailView::Cgroups,
+            DetailView::Cgroups => DetailView::Resources,
+        };
+    }
+
     pub fn on_tick(&mut self, servers: Vec<Server>) {
         self.tabs.update_tabs(&servers);
+        self.backfill_chart_data(&servers);
         self.update_cpu_chart_data();
         self.update_ram_chart_data();
         self.update_network_chart_data();
+        self.update_disk_chart_data();
+        self.update_swap_rates();
 
         if self.last_update_time.elapsed() >= Duration::from_millis(self.update_interval){
             self.update_previous_network_data();
+            self.update_previous_disk_data();
+            self.update_previous_swap_data();
             self.servers = servers;
+            self.forget_removed_servers();
+            self.sort_processes();
             self.last_update_time = Instant::now();
         }
     }
 
+    pub fn showing_resources(&self) -> bool {
+        self.tabs.index != 0 && self.detail_view == DetailView::Resources
+    }
+
+    pub fn showing_processes(&self) -> bool {
+        self.tabs.index != 0 && self.detail_view == DetailView::Processes
+    }
+
+    pub fn showing_cgroups(&self) -> bool {
+        self.tabs.index != 0 && self.detail_view == DetailView::Cgroups
+    }
+
+    pub fn process_sort(&self) -> ProcessSort {
+        *self.process_sort.read().unwrap()
+    }
+
+    /// The server in the currently selected tab, None in the overview
+    pub fn current_server(&self) -> Option<&Server> {
+        self.tabs.index.checked_sub(1).and_then(|index| self.servers.get(index))
+    }
+
+    /// The processes of the server in the currently selected tab
+    pub fn current_processes(&self) -> &[Process] {
+        match self.current_server() {
+            Some(server) => &server.processes,
+            None => &[],
+        }
+    }
+
+    /// The cgroups of the server in the currently selected tab, the biggest consumers first
+    pub fn current_cgroups(&self) -> Vec<&Cgroup> {
+        let mut cgroups: Vec<&Cgroup> = match self.current_server() {
+            Some(server) => server.resources.cgroups.iter().collect(),
+            None => vec![],
+        };
+        self.cgroup_sort.sort(&mut cgroups);
+        cgroups
+    }
+
+    /// Sorts the process lists by the current sort column and keeps the previously
+    /// selected process selected by looking up its pid in the new list
+    fn sort_processes(&mut self) {
+        let sort = self.process_sort();
+        for server in self.servers.iter_mut() {
+            sort.sort(&mut server.processes);
+        }
+
+        let processes = self.current_processes();
+        let size = processes.len();
+        let position = self.process_table.selected_pid
+            .and_then(|pid| processes.iter().position(|process| process.pid == pid));
+        let index = position.or(self.process_table.table.state.selected())
+            .map(|index| index.min(size.saturating_sub(1)))
+            .unwrap_or(0);
+
+        self.process_table.table.size = size;
+        self.process_table.table.state.select(Some(index));
+        self.remember_selected_process();
+    }
+
+    fn remember_selected_process(&mut self) {
+        let selected = self.process_table.table.state.selected();
+        self.process_table.selected_pid = selected
+            .and_then(|index| self.current_processes().get(index))
+            .map(|process| process.pid);
+    }
+
     //TODO: Make the following functions into something more generic to avoid repetition
-    /// Pushes last cpu_data into the vector held in our hashmap and removes all data older than 60 seconds
+    /// Pushes last cpu_data into the vector held in our hashmap and removes all data older than the chart window
     pub fn update_cpu_chart_data(&mut self){
-        for (i, server) in self.servers.iter().enumerate() {
-            if i < self.servers.len() {
-                let chart_data = self.cpu_chart_data
-                    .entry(i)
-                    .or_insert_with(|| vec![0; self.max_chart_data_points]);
-
-                chart_data.push(server.cpu_usage as u64);
-                if chart_data.len() > self.max_chart_data_points {
-                    let index = chart_data.len() - self.max_chart_data_points;
-                    chart_data.drain(..index);
-                }
+        for server in self.servers.iter() {
+            let chart_data = self.cpu_chart_data
+                .entry(server.id)
+                .or_insert_with(|| vec![0; self.max_chart_data_points]);
+
+            chart_data.push(server.resources.cpu_usage as u64);
+            if chart_data.len() > self.max_chart_data_points {
+                let index = chart_data.len() - self.max_chart_data_points;
+                chart_data.drain(..index);
             }
         }
     }
-    /// Pushes last RAM data into the vector held in our hashmap and removes all data older than 60 seconds
+    /// Pushes last RAM data into the vector held in our hashmap and removes all data older than the chart window
     pub fn update_ram_chart_data(&mut self){
-        for (i, server) in self.servers.iter().enumerate() {
-            if i < self.servers.len() {
-                let chart_data = self.ram_chart_data
-                    .entry(i)
-                    .or_insert_with(|| vec![0; self.max_chart_data_points]);
-
-                chart_data.push(used_as_percentage(server.used_memory as f64, server.total_memory as f64) as u64);
-                if chart_data.len() > self.max_chart_data_points {
-                    let index = chart_data.len() - self.max_chart_data_points;
-                    chart_data.drain(..index);
-                }
+        for server in self.servers.iter() {
+            let chart_data = self.ram_chart_data
+                .entry(server.id)
+                .or_insert_with(|| vec![RamSample::default(); self.max_chart_data_points]);
+
+            chart_data.push(RamSample::new(&server.resources));
+            if chart_data.len() > self.max_chart_data_points {
+                let index = chart_data.len() - self.max_chart_data_points;
+                chart_data.drain(..index);
             }
         }
     }
 
     //Saves the previous value for chart comparison
     pub fn update_previous_network_data(&mut self) {
-        for (i, server) in self.servers.iter().enumerate() {
-            let old_transmitted = server.bytes_transmitted;
-            let old_received = server.bytes_received;
+        for server in self.servers.iter() {
+            let totals = self.network_filter.combined(server);
+            let old_transmitted = totals.transmitted;
+            let old_received = totals.received;
 
-            self.previous_transmitted_total.insert(i, old_transmitted);
-            self.previous_received_total.insert(i, old_received);
+            self.previous_transmitted_total.insert(server.id, old_transmitted);
+            self.previous_received_total.insert(server.id, old_received);
+            if let Some(last_success) = server.health.last_success {
+                self.previous_success.insert(server.id, last_success);
+            }
         }
     }
 
     pub fn update_network_chart_data(&mut self) {
-        for (i, server) in self.servers.iter().enumerate() {
-            if i < self.servers.len() {
-                let transmitted_data = self.transmitted_chart_data
-                    .entry(i)
-                    .or_insert_with(|| vec![0.0; self.max_chart_data_points]);
-
-                let received_data = self.received_chart_data
-                    .entry(i)
-                    .or_insert_with(|| vec![0.0; self.max_chart_data_points]);
-
-                let previous_received = self.previous_received_total.get(&i);
-                let previous_transmitted = self.previous_transmitted_total.get(&i);
-
-                if let (Some(previous_received_total), Some(previous_transmitted_total)) = (previous_received, previous_transmitted) {
-                    let tick_rate_sec = self.tick_rate as f64 / 1000.0;
-                    let rx_kb_per_sec = ((server.bytes_received - previous_received_total) as f64) / tick_rate_sec / 1024.0;
-                    let tx_kb_per_sec = ((server.bytes_transmitted - previous_transmitted_total) as f64) / tick_rate_sec / 1024.0;
-
-                    //To avoid showing the total rx/tx on our first calculation
-                    if *previous_received_total != 0 && *previous_transmitted_total != 0 {
-                        received_data.push(rx_kb_per_sec);
-                        transmitted_data.push(tx_kb_per_sec);
-                    }
+        for server in self.servers.iter() {
+            let transmitted_data = self.transmitted_chart_data
+                .entry(server.id)
+                .or_insert_with(|| vec![0.0; self.max_chart_data_points]);
 
+            let received_data = self.received_chart_data
+                .entry(server.id)
+                .or_insert_with(|| vec![0.0; self.max_chart_data_points]);
+
+            let previous_received = self.previous_received_total.get(&server.id);
+            let previous_transmitted = self.previous_transmitted_total.get(&server.id);
+
+            if let (Some(previous_received_total), Some(previous_transmitted_total)) = (previous_received, previous_transmitted) {
+                let totals = self.network_filter.combined(server);
+                let update_interval_sec = seconds_since_previous(&self.previous_success, server, self.update_interval);
+                let (rx_kb_per_sec, tx_kb_per_sec) = match update_interval_sec {
+                    Some(seconds) => (
+                        (totals.received.saturating_sub(*previous_received_total) as f64) / seconds / 1024.0,
+                        (totals.transmitted.saturating_sub(*previous_transmitted_total) as f64) / seconds / 1024.0,
+                    ),
+                    None => (received_data.last().copied().unwrap_or(0.0), transmitted_data.last().copied().unwrap_or(0.0)),
+                };
+
+                //To avoid showing the total rx/tx on our first calculation
+                if *previous_received_total != 0 && *previous_transmitted_total != 0 {
+                    received_data.push(rx_kb_per_sec);
+                    transmitted_data.push(tx_kb_per_sec);
                 }
-                if transmitted_data.len() > self.max_chart_data_points {
-                    transmitted_data.drain(..transmitted_data.len() - self.max_chart_data_points);
-                }
-                if received_data.len() > self.max_chart_data_points {
-                    received_data.drain(..received_data.len() - self.max_chart_data_points);
-                }
+
+            }
+            if transmitted_data.len() > self.max_chart_data_points {
+                transmitted_data.drain(..transmitted_data.len() - self.max_chart_data_points);
+            }
+            if received_data.len() > self.max_chart_data_points {
+                received_data.drain(..received_data.len() - self.max_chart_data_points);
             }
         }
     }
+
+    /// Saves the previous disk totals for the rate charts. Servers that haven't reported their disks yet
+    /// are skipped, so their first rate isn't taken from zero
+    pub fn update_previous_disk_data(&mut self) {
+        for server in self.servers.iter().filter(|server| !server.resources.disk_io.is_empty()) {
+            let totals = self.disk_filter.combined(server);
+            self.previous_disk_read_total.insert(server.id, totals.read_bytes);
+            self.previous_disk_written_total.insert(server.id, totals.written_bytes);
+        }
+    }
+
+    /// Pushes the read and write rates since the previous totals and removes all data older than the chart window
+    pub fn update_disk_chart_data(&mut self) {
+        for server in self.servers.iter() {
+            let read_data = self.disk_read_chart_data
+                .entry(server.id)
+                .or_insert_with(|| vec![0.0; self.max_chart_data_points]);
+            let written_data = self.disk_written_chart_data
+                .entry(server.id)
+                .or_insert_with(|| vec![0.0; self.max_chart_data_points]);
+
+            let previous_read = self.previous_disk_read_total.get(&server.id);
+            let previous_written = self.previous_disk_written_total.get(&server.id);
+            if let (Some(previous_read), Some(previous_written)) = (previous_read, previous_written) {
+                let totals = self.disk_filter.combined(server);
+                let (read_per_sec, written_per_sec) = match seconds_since_previous(&self.previous_success, server, self.update_interval) {
+                    Some(seconds) => (
+                        totals.read_bytes.saturating_sub(*previous_read) as f64 / seconds,
+                        totals.written_bytes.saturating_sub(*previous_written) as f64 / seconds,
+                    ),
+                    None => (read_data.last().copied().unwrap_or(0.0), written_data.last().copied().unwrap_or(0.0)),
+                };
+                read_data.push(read_per_sec);
+                written_data.push(written_per_sec);
+            }
+            if read_data.len() > self.max_chart_data_points {
+                read_data.drain(..read_data.len() - self.max_chart_data_points);
+            }
+            if written_data.len() > self.max_chart_data_points {
+                written_data.drain(..written_data.len() - self.max_chart_data_points);
+            }
+        }
+    }
+
+    /// Saves how much the servers have swapped so far. Servers that don't report it are skipped
+    pub fn update_previous_swap_data(&mut self) {
+        for server in self.servers.iter() {
+            if let Some(memory) = &server.resources.memory {
+                self.previous_swapped.insert(server.id, (memory.swapped_in, memory.swapped_out));
+            }
+        }
+    }
+
+    /// Takes the swap rates since the previous totals, the last rates are kept while there's no new answer
+    pub fn update_swap_rates(&mut self) {
+        for server in self.servers.iter() {
+            let (Some(memory), Some((previous_in, previous_out))) = (&server.resources.memory, self.previous_swapped.get(&server.id)) else {
+                continue;
+            };
+            if let Some(seconds) = seconds_since_previous(&self.previous_success, server, self.update_interval) {
+                self.swap_rates.insert(server.id, (
+                    memory.swapped_in.saturating_sub(*previous_in) as f64 / seconds,
+                    memory.swapped_out.saturating_sub(*previous_out) as f64 / seconds,
+                ));
+            }
+        }
+    }
+
+    /// Fills the charts of newly connected servers with the history recorded by their agent,
+    /// so the charts don't start out as a flat line of zeros
+    fn backfill_chart_data(&mut self, servers: &[Server]) {
+        for server in servers {
+            if server.history.is_empty() || !self.backfilled.insert(server.id) {
+                continue;
+            }
+            let samples = resample_history(&server.history, self.max_chart_data_points, self.tick_rate);
+
+            self.cpu_chart_data.insert(server.id, samples.iter()
+                .map(|sample| sample.map(|(_, sample)| sample.cpu_usage as u64).unwrap_or(0))
+                .collect());
+            self.ram_chart_data.insert(server.id, samples.iter()
+                .map(|sample| sample.map(|(_, sample)| RamSample {
+                    used: used_as_percentage(sample.used_memory as f64, sample.total_memory as f64),
+                    ..Default::default()
+                }).unwrap_or_default())
+                .collect());
+
+            // The history only holds the sum of all interfaces
+            if self.network_filter == NetworkFilter::All {
+                let rate = |index: usize, counter: fn(&HistorySample) -> u64| {
+                    let current = &server.history[index];
+                    let previous = &server.history[index.checked_sub(1)?];
+                    let seconds = current.timestamp.saturating_sub(previous.timestamp) as f64 / 1000.0;
+                    if seconds <= 0.0 {
+                        return None;
+                    }
+                    Some(counter(current).saturating_sub(counter(previous)) as f64 / seconds / 1024.0)
+                };
+                self.received_chart_data.insert(server.id, samples.iter()
+                    .map(|sample| sample.and_then(|(index, _)| rate(index, |s| s.bytes_received)).unwrap_or(0.0))
+                    .collect());
+                self.transmitted_chart_data.insert(server.id, samples.iter()
+                    .map(|sample| sample.and_then(|(index, _)| rate(index, |s| s.bytes_transmitted)).unwrap_or(0.0))
+                    .collect());
+            }
+            // The same goes for the disks, and the rates are kept in bytes rather than KB
+            if self.disk_filter == DiskFilter::All {
+                let rate = |index: usize, counter: fn(&HistorySample) -> u64| {
+                    let current = &server.history[index];
+                    let previous = &server.history[index.checked_sub(1)?];
+                    let seconds = current.timestamp.saturating_sub(previous.timestamp) as f64 / 1000.0;
+                    if seconds <= 0.0 {
+                        return None;
+                    }
+                    Some(counter(current).saturating_sub(counter(previous)) as f64 / seconds)
+                };
+                self.disk_read_chart_data.insert(server.id, samples.iter()
+                    .map(|sample| sample.and_then(|(index, _)| rate(index, |s| s.disk_read_bytes)).unwrap_or(0.0))
+                    .collect());
+                self.disk_written_chart_data.insert(server.id, samples.iter()
+                    .map(|sample| sample.and_then(|(index, _)| rate(index, |s| s.disk_written_bytes)).unwrap_or(0.0))
+                    .collect());
+            }
+        }
+    }
+
+    /// Starts the network charts over, used when the totals they are based on change meaning
+    fn reset_network_chart_data(&mut self) {
+        self.previous_received_total.clear();
+        self.previous_transmitted_total.clear();
+        self.received_chart_data.clear();
+        self.transmitted_chart_data.clear();
+    }
+
+    /// Starts the disk charts over when another disk is selected
+    fn reset_disk_chart_data(&mut self) {
+        self.previous_disk_read_total.clear();
+        self.previous_disk_written_total.clear();
+        self.disk_read_chart_data.clear();
+        self.disk_written_chart_data.clear();
+    }
+}
+
+/// The seconds between the server's answer with the previous totals and its latest answer. The previous
+/// totals are normally from one update interval ago, but servers with a poll interval of their own
+/// answer less often. None when nothing new arrived since, so the rates haven't changed either
+fn seconds_since_previous(previous_success: &HashMap<ServerId, Instant>, server: &Server, update_interval: u64) -> Option<f64> {
+    match (previous_success.get(&server.id), server.health.last_success) {
+        (Some(previous), Some(latest)) if latest > *previous => Some((latest - *previous).as_secs_f64()),
+        (Some(previous), Some(latest)) if latest == *previous => None,
+        _ => Some(update_interval as f64 / 1000.0),
+    }
+}
+
+/// The sort column after the given one, wrapping around
+pub fn next_process_sort(sort: ProcessSort) -> ProcessSort {
+    let index = ProcessSort::ALL.iter().position(|other| *other == sort).unwrap_or(0);
+    ProcessSort::ALL[(index + 1) % ProcessSort::ALL.len()]
+}
+
+/// Spreads the history samples over `points` chart points one tick apart, ending at the newest sample.
+/// Each point gets the newest sample taken at or before it, together with its index in the history
+fn resample_history(history: &[HistorySample], points: usize, tick_rate: u64) -> Vec<Option<(usize, &HistorySample)>> {
+    let latest = match history.last() {
+        Some(sample) => sample.timestamp,
+        None => return vec![None; points],
+    };
+    (0..points)
+        .map(|point| {
+            let time = latest.saturating_sub((points - 1 - point) as u64 * tick_rate);
+            history.iter()
+                .enumerate()
+                .rev()
+                .find(|(_, sample)| sample.timestamp <= time)
+        })
+        .collect()
+}
+
+/// Selects which network interfaces the network charts and totals are based on
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetworkFilter {
+    All,
+    /// Every interface except loopback and virtual ones such as docker bridges
+    Physical,
+    Interface(String),
+}
+
+impl NetworkFilter {
+    pub fn title(&self) -> String {
+        match self {
+            NetworkFilter::All => String::from("All interfaces"),
+            NetworkFilter::Physical => String::from("Physical interfaces"),
+            NetworkFilter::Interface(name) => name.clone(),
+        }
+    }
+
+    /// Cycles through all, physical and then each interface of the server
+    pub fn next(&self, server: &Server) -> NetworkFilter {
+        let mut names = server.resources.networks.iter().map(|interface| &interface.name);
+        match self {
+            NetworkFilter::All => NetworkFilter::Physical,
+            NetworkFilter::Physical => names.next()
+                .map(|name| NetworkFilter::Interface(name.clone()))
+                .unwrap_or(NetworkFilter::All),
+            NetworkFilter::Interface(current) => names.skip_while(|name| *name != current)
+                .nth(1)
+                .map(|name| NetworkFilter::Interface(name.clone()))
+                .unwrap_or(NetworkFilter::All),
+        }
+    }
+
+    /// Sums up the counters of the interfaces matching the filter.
+    /// Agents that don't report their interfaces only give us the sum of all of them
+    pub fn combined(&self, server: &Server) -> NetworkInterface {
+        if server.resources.networks.is_empty() {
+            return NetworkInterface {
+                name: self.title(),
+                received: server.resources.bytes_received,
+                transmitted: server.resources.bytes_transmitted,
+                ..Default::default()
+            };
+        }
+
+        server.resources.networks.iter()
+            .filter(|interface| match self {
+                NetworkFilter::All => true,
+                NetworkFilter::Physical => !interface.is_loopback && !interface.is_virtual,
+                NetworkFilter::Interface(name) => &interface.name == name,
+            })
+            .fold(NetworkInterface { name: self.title(), ..Default::default() }, |mut total, interface| {
+                total.received += interface.received;
+                total.transmitted += interface.transmitted;
+                total.packets_received += interface.packets_received;
+                total.packets_transmitted += interface.packets_transmitted;
+                total.errors_received += interface.errors_received;
+                total.errors_transmitted += interface.errors_transmitted;
+                total.drops_received += interface.drops_received;
+                total.drops_transmitted += interface.drops_transmitted;
+                total
+            })
+    }
+}
+
+/// A point of the RAM chart, each part in percent of the total memory
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RamSample {
+    /// What processes use, or everything in use when the agent doesn't break the memory down.
+    /// The history only has the latter, so backfilled points have nothing else
+    pub used: f64,
+    pub buffers: f64,
+    pub cached: f64,
+    /// Part of the cache
+    pub shared: f64,
+    /// Part of the cache
+    pub dirty: f64,
+}
+
+impl RamSample {
+    pub fn new(resources: &Resources) -> Self {
+        let total = resources.total_memory as f64;
+        let percent = |bytes: u64| used_as_percentage(bytes as f64, total);
+        match &resources.memory {
+            // The breakdown is of the host's memory, which doesn't add up to the cgroup's
+            Some(memory) if !resources.memory_limited() => RamSample {
+                // Counted the way free does
+                used: percent(resources.total_memory.saturating_sub(memory.free + memory.buffers + memory.cached)),
+                buffers: percent(memory.buffers),
+                cached: percent(memory.cached),
+                shared: percent(memory.shared),
+                dirty: percent(memory.dirty),
+            },
+            _ => RamSample { used: percent(resources.used_memory), ..Default::default() },
+        }
+    }
+}
+
+/// The column the cgroup table is sorted by, in descending order
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CgroupSort {
+    #[default]
+    Cpu,
+    Memory,
+    /// Bytes read and written together
+    Io,
+    Pids,
+}
+
+impl CgroupSort {
+    pub fn next(self) -> CgroupSort {
+        match self {
+            CgroupSort::Cpu => CgroupSort::Memory,
+            CgroupSort::Memory => CgroupSort::Io,
+            CgroupSort::Io => CgroupSort::Pids,
+            CgroupSort::Pids => CgroupSort::Cpu,
+        }
+    }
+
+    pub fn sort(self, cgroups: &mut [&Cgroup]) {
+        match self {
+            CgroupSort::Cpu => cgroups.sort_by(|a, b| b.cpu_usage.total_cmp(&a.cpu_usage)),
+            CgroupSort::Memory => cgroups.sort_by_key(|cgroup| Reverse(cgroup.memory_current)),
+            CgroupSort::Io => cgroups.sort_by_key(|cgroup| Reverse(cgroup.io_read_bytes + cgroup.io_written_bytes)),
+            CgroupSort::Pids => cgroups.sort_by_key(|cgroup| Reverse(cgroup.pids)),
+        }
+    }
+}
+
+impl fmt::Display for CgroupSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let title = match self {
+            CgroupSort::Cpu => "CPU",
+            CgroupSort::Memory => "Memory",
+            CgroupSort::Io => "I/O",
+            CgroupSort::Pids => "PIDs",
+        };
+        write!(f, "{}", title)
+    }
+}
+
+/// Selects which block devices the disk I/O chart is based on
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiskFilter {
+    All,
+    Device(String),
+}
+
+impl DiskFilter {
+    pub fn title(&self) -> String {
+        match self {
+            DiskFilter::All => String::from("All disks"),
+            DiskFilter::Device(name) => name.clone(),
+        }
+    }
+
+    /// Cycles through all and then each block device of the server
+    pub fn next(&self, server: &Server) -> DiskFilter {
+        let mut names = server.resources.disk_io.iter().map(|disk| &disk.name);
+        let next = match self {
+            DiskFilter::All => names.next(),
+            DiskFilter::Device(current) => names.skip_while(|name| *name != current).nth(1),
+        };
+        next.map(|name| DiskFilter::Device(name.clone())).unwrap_or(DiskFilter::All)
+    }
+
+    /// Sums up the counters of the devices matching the filter
+    pub fn combined(&self, server: &Server) -> DiskIo {
+        server.resources.disk_io.iter()
+            .filter(|disk| match self {
+                DiskFilter::All => true,
+                DiskFilter::Device(name) => &disk.name == name,
+            })
+            .fold(DiskIo { name: self.title(), ..Default::default() }, |mut total, disk| {
+                total.read_bytes += disk.read_bytes;
+                total.written_bytes += disk.written_bytes;
+                total.reads += disk.reads;
+                total.writes += disk.writes;
+                total.read_time += disk.read_time;
+                total.write_time += disk.write_time;
+                total.io_time += disk.io_time;
+                total
+            })
+    }
 }
 
 pub struct ScrollState {
     pub vertical_scroll_state: ScrollbarState,
     pub scroll_pos: u16,
     pub scroll_content_length: u16,
+    /// How many servers fit in the overview, set when it is drawn
+    pub view_length: u16,
+    /// The server in the overview that edits apply to, always kept in view
+    pub selected: u16,
 }
 
 impl ScrollState{
@@ -193,32 +898,50 @@ impl ScrollState{
             vertical_scroll_state: Default::default(),
             scroll_pos: 0,
             scroll_content_length: 0,
+            view_length: 1,
+            selected: 0,
         }
     }
 
     pub fn up(&mut self){
-        if self.scroll_pos > 0 {
-            self.scroll_pos -= 1;
-            self.vertical_scroll_state = self.vertical_scroll_state.position(self.scroll_pos as usize);
+        if self.selected > 0 {
+            self.selected -= 1;
+        }
+        if self.selected < self.scroll_pos {
+            self.scroll_pos = self.selected;
         }
+        self.vertical_scroll_state = self.vertical_scroll_state.position(self.scroll_pos as usize);
     }
 
     pub fn down(&mut self) {
-        if self.scroll_pos < self.scroll_content_length {
-            self.scroll_pos += 1;
-            self.vertical_scroll_state = self.vertical_scroll_state.position(self.scroll_pos as usize);
+        if self.selected + 1 < self.scroll_content_length {
+            self.selected += 1;
         }
+        if self.selected >= self.scroll_pos + self.view_length {
+            self.scroll_pos = self.selected + 1 - self.view_length;
+        }
+        self.vertical_scroll_state = self.vertical_scroll_state.position(self.scroll_pos as usize);
+    }
+
+    /// Keeps the selection and the scroll position within the servers after their number changed
+    pub fn clamp(&mut self, content_length: u16) {
+        self.scroll_content_length = content_length;
+        self.selected = self.selected.min(content_length.saturating_sub(1));
+        self.scroll_pos = self.scroll_pos.min(self.selected);
+        self.vertical_scroll_state = self.vertical_scroll_state.position(self.scroll_pos as usize);
     }
 }
 
 pub struct TabsState {
     pub titles: Vec<String>,
+    /// The server of each tab after the overview
+    pub ids: Vec<ServerId>,
     pub index: usize,
 }
 
 impl TabsState {
     pub fn new() -> TabsState {
-        TabsState { titles: vec![], index: 0 }
+        TabsState { titles: vec![], ids: vec![], index: 0 }
     }
     pub fn next(&mut self) {
         self.index = (self.index + 1) % self.titles.len();
@@ -231,13 +954,20 @@ impl TabsState {
             self.index = self.titles.len() - 1;
         }
     }
-    pub fn update_tabs(&mut self, servers: &Vec<Server>) {
+    /// Stays on the same server if it moved, and goes back to the overview if it was removed
+    pub fn update_tabs(&mut self, servers: &[Server]) {
+        let selected = self.index.checked_sub(1).and_then(|index| self.ids.get(index)).copied();
         let mut names: Vec<String> = vec![];
         names.push(String::from("Overview"));
         for server in servers{
-            names.push(server.hostname.chars().take(10).collect());
+            let name = server.options.name.as_deref().unwrap_or(&server.resources.hostname);
+            names.push(name.chars().take(10).collect());
         }
         self.titles = names;
+        self.ids = servers.iter().map(|server| server.id).collect();
+        if let Some(selected) = selected {
+            self.index = self.ids.iter().position(|id| *id == selected).map_or(0, |index| index + 1);
+        }
     }
 }
 
@@ -287,9 +1017,37 @@ impl CpuTable {
     }
 }
 
+/// Navigation for the process table. The pid of the selected process is remembered
+/// so the selection follows the process when the list is refreshed or re-sorted
+pub struct ProcessTable {
+    pub table: CpuTable,
+    pub selected_pid: Option<u32>,
+}
+
+impl ProcessTable {
+    pub fn new() -> ProcessTable {
+        ProcessTable {
+            table: CpuTable::new(),
+            selected_pid: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.table.state.select(Some(0));
+        self.selected_pid = None;
+    }
+}
+
 pub struct InputState{
     pub input: String,
     pub cursor_position: usize,
+    /// Why the last input couldn't be added, shown until the input is changed
+    pub error: Option<String>,
+    /// The server being edited, None when adding a server
+    pub editing: Option<ServerId>,
+    /// The token of the server being edited, left out of the input so it isn't shown on screen.
+    /// Kept unless a token is typed
+    kept_token: Option<String>,
 }
 
 impl InputState {
@@ -297,9 +1055,28 @@ impl InputState {
         InputState{
             input: String::new(),
             cursor_position: 0,
+            error: None,
+            editing: None,
+            kept_token: None,
         }
     }
 
+    /// Starts over with the given input and the cursor at its end
+    pub(crate) fn start(&mut self, editing: Option<ServerId>, input: String) {
+        self.cursor_position = input.chars().count();
+        self.input = input;
+        self.error = None;
+        self.editing = editing;
+        self.kept_token = None;
+    }
+
+    /// Starts over with the endpoint and options of the server, except for its token
+    pub(crate) fn start_editing(&mut self, server: &Server) {
+        let options = EndpointOptions { token: None, ..server.options.clone() };
+        self.start(Some(server.id), format_endpoint(&server.endpoint, &options));
+        self.kept_token = server.options.token.clone();
+    }
+
     pub(crate) fn move_cursor_left(&mut self) {
         let cursor_moved_left = self.cursor_position.saturating_sub(1);
         self.cursor_position = self.clamp_cursor(cursor_moved_left);
@@ -311,6 +1088,7 @@ impl InputState {
     }
 
     pub(crate) fn enter_char(&mut self, new_char: char) {
+        self.error = None;
         self.input.insert(self.cursor_position, new_char);
         self.move_cursor_right();
     }
@@ -318,6 +1096,7 @@ impl InputState {
     pub(crate) fn delete_char(&mut self) {
         let is_not_cursor_leftmost = self.cursor_position != 0;
         if is_not_cursor_leftmost {
+            self.error = None;
             // Method "remove" is not used on the saved text for deleting the selected char.
             // Reason: Using remove on String works on bytes instead of the chars.
             // Using remove would require special care because of char boundaries.
@@ -345,11 +1124,180 @@ impl InputState {
         self.cursor_position = 0;
     }
 
-    //TODO: INIT A SERVER FROM ENDPOINT INPUT AND PUSH TO Vec<Server>
-    pub(crate) fn add_endpoint(&mut self) -> Server {
-        let new_server = Server::new(self.input.clone());
+    /// Parses the input the same way as a line of an endpoint file, so options such as a token
+    /// can be given after the URL. Gives None and keeps the input if it isn't a valid endpoint
+    pub(crate) fn take_server(&mut self) -> Option<Server> {
+        let (endpoint, mut options) = match parse_endpoint(&self.input) {
+            Ok(Some(endpoint)) => endpoint,
+            Ok(None) => {
+                self.error = Some(String::from("No endpoint given"));
+                return None;
+            }
+            Err(err) => {
+                self.error = Some(err);
+                return None;
+            }
+        };
+        if options.token.is_none() {
+            options.token = self.kept_token.take();
+        }
         self.input.clear();
         self.reset_cursor();
-        new_server
+        Some(Server::new(endpoint, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Mutex;
+    use tokio::time::sleep;
+    use crate::server::tests::{apply_in_background, mock_agent};
+
+    /// How long the mock agents take to list their processes, which keeps an update in flight
+    const PROCESSES_DELAY: Duration = Duration::from_millis(300);
+
+    /// An app showing the servers a, b and c, each with a CPU chart starting with a value of its
+    /// own, with the tab of b open. Also returns the servers shared with the refresher
+    async fn app_with_servers() -> (App, Arc<Mutex<Vec<Server>>>) {
+        let mut servers = vec![];
+        for hostname in ["a", "b", "c"] {
+            let endpoint = mock_agent(hostname, "processes", PROCESSES_DELAY, Arc::default()).await;
+            let mut server = Server::new(endpoint, EndpointOptions::default());
+            server.resources.hostname = hostname.to_string();
+            servers.push(server);
+        }
+        let appearance = Appearance { theme: Theme::default(), units: Units::default(), chart_window: Duration::from_secs(60) };
+        let mut app = App::new(String::new(), 250, 0, Arc::default(), appearance);
+        for (first_value, server) in servers.iter().enumerate() {
+            app.cpu_chart_data.insert(server.id, vec![first_value as u64 + 1]);
+        }
+        app.servers = servers.clone();
+        app.tabs.update_tabs(&servers);
+        app.tabs.index = 2;
+        (app, Arc::new(Mutex::new(servers)))
     }
-}
\ No newline at end of file
+
+    /// Makes the edit in the UI while an update of the server at the index is in flight, then
+    /// applies the queued edits to the shared servers and ticks, the way the event loop does
+    async fn edit_during_update(app: &mut App, shared: &Arc<Mutex<Vec<Server>>>, index: usize, edit: ServerEdit) {
+        let server = app.servers[index].clone();
+        let update = Resources { hostname: format!("{} updated", server.resources.hostname), ..Default::default() };
+        let apply = apply_in_background(shared.clone(), &server, update);
+        sleep(PROCESSES_DELAY / 3).await;
+        assert!(!apply.is_finished(), "the update landed before the edit was made");
+
+        app.edit_servers(edit);
+        for edit in app.server_edits.drain(..) {
+            edit.apply(&mut *shared.lock().await);
+        }
+        apply.await.unwrap();
+        app.on_tick(shared.lock().await.to_vec());
+    }
+
+    /// The hostname of each server with the first value of its CPU chart
+    fn charts(app: &App) -> Vec<(&str, u64)> {
+        app.servers.iter()
+            .map(|server| (server.resources.hostname.as_str(), app.cpu_chart_data[&server.id][0]))
+            .collect()
+    }
+
+    fn ids(servers: &[Server]) -> Vec<ServerId> {
+        servers.iter().map(|server| server.id).collect()
+    }
+
+    #[tokio::test]
+    async fn removing_a_server_being_updated() {
+        let (mut app, shared) = app_with_servers().await;
+        let removed = app.servers[1].id;
+        edit_during_update(&mut app, &shared, 1, ServerEdit::Remove(removed)).await;
+
+        assert_eq!(ids(&shared.lock().await), ids(&app.servers));
+        assert_eq!(charts(&app), [("a", 1), ("c", 3)]);
+        assert!(!app.cpu_chart_data.contains_key(&removed));
+        assert_eq!(app.tabs.ids, ids(&app.servers));
+        assert_eq!(app.tabs.index, 0, "the tab of the removed server is still open");
+    }
+
+    #[tokio::test]
+    async fn swapping_a_server_being_updated() {
+        let (mut app, shared) = app_with_servers().await;
+        let edit = ServerEdit::Swap(app.servers[0].id, app.servers[1].id);
+        edit_during_update(&mut app, &shared, 1, edit).await;
+
+        assert_eq!(ids(&shared.lock().await), ids(&app.servers));
+        assert_eq!(charts(&app), [("b updated", 2), ("a", 1), ("c", 3)]);
+        assert_eq!(app.tabs.ids, ids(&app.servers));
+        assert_eq!(app.tabs.index, 1, "the open tab didn't follow the server");
+        assert_eq!(app.tabs.titles[1], "b updated");
+    }
+
+    #[tokio::test]
+    async fn adding_a_server_while_another_is_updated() {
+        let (mut app, shared) = app_with_servers().await;
+        let added = Server::new(String::from("http://127.0.0.1:1/resources"), EndpointOptions::default());
+        edit_during_update(&mut app, &shared, 1, ServerEdit::Add(added)).await;
+
+        assert_eq!(ids(&shared.lock().await), ids(&app.servers));
+        assert_eq!(charts(&app), [("a", 1), ("b updated", 2), ("c", 3), ("", 0)]);
+        assert_eq!(app.tabs.ids, ids(&app.servers));
+        assert_eq!(app.tabs.index, 2);
+    }
+
+    #[tokio::test]
+    async fn replacing_the_endpoint_of_a_server_being_updated() {
+        let (mut app, shared) = app_with_servers().await;
+        let replaced = app.servers[1].id;
+        let replacement = Server::new(String::from("http://127.0.0.1:1/resources"), EndpointOptions::default());
+        let replacement_id = replacement.id;
+        edit_during_update(&mut app, &shared, 1, ServerEdit::Replace(replaced, replacement)).await;
+
+        // The update was for the old endpoint, so it mustn't show up as the data of the new one
+        assert_eq!(ids(&shared.lock().await), ids(&app.servers));
+        assert_eq!(app.servers[1].id, replacement_id);
+        assert_eq!(charts(&app), [("a", 1), ("", 0), ("c", 3)]);
+        assert!(!app.cpu_chart_data.contains_key(&replaced));
+        assert_eq!(app.tabs.ids, ids(&app.servers));
+        assert_eq!(app.tabs.index, 0);
+    }
+
+    #[tokio::test]
+    async fn replacing_the_options_of_a_server_being_updated() {
+        let (mut app, shared) = app_with_servers().await;
+        let id = app.servers[1].id;
+        let options = EndpointOptions { name: Some(String::from("bee")), ..Default::default() };
+        let renamed = Server::new(app.servers[1].endpoint.clone(), options);
+        edit_during_update(&mut app, &shared, 1, ServerEdit::Replace(id, renamed)).await;
+
+        assert_eq!(ids(&shared.lock().await), ids(&app.servers));
+        assert_eq!(app.servers[1].id, id, "the server got a new id although its endpoint stayed the same");
+        assert_eq!(app.servers[1].name(), "bee");
+        assert_eq!(charts(&app), [("a", 1), ("b updated", 2), ("c", 3)]);
+        assert_eq!(app.tabs.index, 2);
+        assert_eq!(app.tabs.titles[2], "bee");
+    }
+
+    #[test]
+    fn editing_keeps_the_token_out_of_sight() {
+        let options = EndpointOptions { token: Some(String::from("secret")), name: Some(String::from("bee")), ..Default::default() };
+        let server = Server::new(String::from("http://127.0.0.1:3000/resources"), options);
+        let mut input = InputState::default();
+        input.start_editing(&server);
+        assert_eq!(input.input, "http://127.0.0.1:3000/resources name=bee");
+        assert_eq!(input.cursor_position, input.input.chars().count());
+
+        let edited = input.take_server().unwrap();
+        assert_eq!(edited.options, server.options);
+
+        input.start_editing(&server);
+        for c in " token=other".chars() {
+            input.enter_char(c);
+        }
+        assert_eq!(input.take_server().unwrap().options.token.as_deref(), Some("other"));
+
+        // Adding a server afterwards doesn't pick up the token of the one edited before
+        input.start_editing(&server);
+        input.start(None, String::from("http://127.0.0.1:3001/resources"));
+        assert_eq!(input.take_server().unwrap().options.token, None);
+    }
+}
@@ -1,4 +1,11 @@
+use std::path::PathBuf;
 use clap:: {Parser};
+use crate::server::FetchMode;
+
+pub const DEFAULT_TICK_RATE: u64 = 250;
+pub const DEFAULT_UPDATE_FREQUENCY: u64 = 1000;
+/// In seconds
+pub const DEFAULT_CHART_WINDOW: u64 = 60;
 
 #[derive(Parser)]
 #[group(multiple = true)]
@@ -8,11 +15,52 @@ pub struct Args {
     #[arg()]
     pub files: Vec<String>,
 
-    /// The UI tick rate
-    #[arg(short, long("tick rate"), value_name = "milliseconds", default_value = "250")]
-    pub tick_rate: u64,
+    /// Read settings and endpoints from this TOML file instead of ~/.config/resource-monitor/dashboard.toml
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// The UI tick rate, 250 unless set in the config file
+    #[arg(short, long("tick rate"), value_name = "milliseconds")]
+    pub tick_rate: Option<u64>,
+
+    /// How often we fetch new data from server endpoints, 1000 unless set in the config file
+    #[arg(short, long, value_name = "milliseconds")]
+    pub update_frequency: Option<u64>,
+
+    /// How many processes to fetch from each server for the process table
+    #[arg(short, long, value_name = "processes", default_value = "100")]
+    pub process_limit: usize,
+
+    /// Whether to poll the endpoints or keep a stream open to them. Agents that don't
+    /// support streaming are polled either way
+    #[arg(short, long, value_enum, default_value = "stream")]
+    pub mode: FetchMode,
+
+    /// How many endpoints are polled at the same time
+    #[arg(short, long, value_name = "requests", default_value = "16")]
+    pub concurrency: usize,
+
+    /// How long to wait for an endpoint to answer a request before giving up on it
+    #[arg(long, value_name = "milliseconds", default_value = "2000")]
+    pub timeout: u64,
+
+    /// Trust the CAs in this PEM bundle, on top of the system's, when connecting over HTTPS
+    #[arg(long, value_name = "PATH")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Present this PEM client certificate to agents that require one
+    #[arg(long, value_name = "PATH", requires = "client_key")]
+    pub client_cert: Option<PathBuf>,
+
+    /// The PEM private key of the client certificate
+    #[arg(long, value_name = "PATH", requires = "client_cert")]
+    pub client_key: Option<PathBuf>,
+
+    /// Where 'w' and --autosave write the servers to, defaults to the first endpoint file
+    #[arg(long, value_name = "PATH")]
+    pub save_to: Option<PathBuf>,
 
-    /// How often we fetch new data from server endpoints
-    #[arg(short, long, value_name = "milliseconds", default_value = "1000")]
-    pub update_frequency: u64,
+    /// Save the servers when the dashboard exits
+    #[arg(long)]
+    pub autosave: bool,
 }
\ No newline at end of file
@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Deserializer};
+use serde::de::Error;
+use schema::config::describe_error;
+use crate::args::{Args, DEFAULT_TICK_RATE, DEFAULT_UPDATE_FREQUENCY};
+use crate::server::{EndpointOptions, Server};
+use crate::theme::Theme;
+use crate::util::Units;
+
+/// The dashboard's config file, every setting is optional. Settings given on the command line win
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The UI tick rate in milliseconds
+    #[serde(default, deserialize_with = "positive")]
+    pub tick_rate: Option<u64>,
+    /// How often we fetch new data from server endpoints, in milliseconds
+    #[serde(default, deserialize_with = "positive")]
+    pub update_frequency: Option<u64>,
+    #[serde(default)]
+    pub theme: Theme,
+    #[serde(default)]
+    pub units: Units,
+    /// How many seconds the charts go back
+    #[serde(default, deserialize_with = "positive")]
+    pub chart_window: Option<u64>,
+    /// The `[[server]]` tables
+    #[serde(default, rename = "server")]
+    pub servers: Vec<ServerConfig>,
+}
+
+/// A `[[server]]` table, an endpoint with its settings
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    #[serde(deserialize_with = "not_empty")]
+    pub url: String,
+    #[serde(default, deserialize_with = "optional_not_empty")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, deserialize_with = "optional_not_empty")]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub insecure: bool,
+    /// In milliseconds
+    #[serde(default, deserialize_with = "positive")]
+    pub timeout: Option<u64>,
+    /// In milliseconds
+    #[serde(default, deserialize_with = "positive")]
+    pub poll_interval: Option<u64>,
+}
+
+impl ServerConfig {
+    pub fn into_server(self) -> Server {
+        Server::new(self.url, EndpointOptions {
+            token: self.token,
+            insecure: self.insecure,
+            name: self.name,
+            tags: self.tags,
+            timeout: self.timeout.map(Duration::from_millis),
+            poll_interval: self.poll_interval.map(Duration::from_millis),
+        })
+    }
+}
+
+impl Config {
+    /// Reads the config file given with --config, or the one in the user's config directory if there is one.
+    /// No file means the defaults, but a file that can't be read or parsed is an error
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read config file {}: {}", path.display(), err))?;
+        toml::from_str(&contents).map_err(|err| describe_error(&path, &contents, err.message(), err.span()))
+    }
+
+    /// The tick rate given on the command line, in the file or the default, in milliseconds
+    pub fn tick_rate(&self, args: &Args) -> u64 {
+        args.tick_rate.or(self.tick_rate).unwrap_or(DEFAULT_TICK_RATE)
+    }
+
+    /// The update frequency given on the command line, in the file or the default, in milliseconds
+    pub fn update_frequency(&self, args: &Args) -> u64 {
+        args.update_frequency.or(self.update_frequency).unwrap_or(DEFAULT_UPDATE_FREQUENCY)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/resource-monitor/dashboard.toml`, usually `~/.config/resource-monitor/dashboard.toml`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("resource-monitor").join("dashboard.toml"))
+}
+
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match u64::deserialize(deserializer)? {
+        0 => Err(D::Error::custom("must be above 0")),
+        value => Ok(Some(value)),
+    }
+}
+
+fn not_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    if value.trim().is_empty() {
+        return Err(D::Error::custom("can't be empty"));
+    }
+    Ok(value)
+}
+
+fn optional_not_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    not_empty(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use super::*;
+
+    const FULL: &str = r#"
+tick_rate = 100
+update_frequency = 2000
+theme = "light"
+units = "decimal"
+chart_window = 300
+
+[[server]]
+url = "https://web1:3000"
+name = "web"
+tags = ["prod", "eu"]
+token = "secret"
+insecure = true
+timeout = 500
+poll_interval = 5000
+
+[[server]]
+url = "http://db1:3000"
+"#;
+
+    /// Loads the contents as a config file
+    fn load(name: &str, contents: &str) -> Config {
+        let path = std::env::temp_dir().join(format!("dashboard-config-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let config = Config::load(Some(&path));
+        fs::remove_file(&path).unwrap();
+        config.unwrap()
+    }
+
+    #[test]
+    fn full_config() {
+        let config = load("full", FULL);
+        assert_eq!(config.theme, Theme::Light);
+        assert_eq!(config.units, Units::Decimal);
+        assert_eq!(config.chart_window, Some(300));
+
+        let servers: Vec<Server> = config.servers.into_iter().map(ServerConfig::into_server).collect();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].endpoint, "https://web1:3000");
+        assert_eq!(servers[0].options, EndpointOptions {
+            token: Some("secret".to_string()),
+            insecure: true,
+            name: Some("web".to_string()),
+            tags: vec!["prod".to_string(), "eu".to_string()],
+            timeout: Some(Duration::from_millis(500)),
+            poll_interval: Some(Duration::from_millis(5000)),
+        });
+        assert_eq!(servers[1].endpoint, "http://db1:3000");
+        assert_eq!(servers[1].options, EndpointOptions::default());
+    }
+
+    #[test]
+    fn flags_win_over_the_file() {
+        let config = load("flags", FULL);
+        let args = Args::parse_from(["dashboard", "--update-frequency", "500"]);
+        assert_eq!(config.update_frequency(&args), 500);
+        assert_eq!(config.tick_rate(&args), 100);
+    }
+
+    #[test]
+    fn only_defaults() {
+        let config = load("defaults", "");
+        let args = Args::parse_from(["dashboard"]);
+        assert_eq!(config.tick_rate(&args), DEFAULT_TICK_RATE);
+        assert_eq!(config.update_frequency(&args), DEFAULT_UPDATE_FREQUENCY);
+        assert_eq!(config.theme, Theme::Dark);
+        assert_eq!(config.units, Units::Binary);
+        assert_eq!(config.chart_window, None);
+        assert!(config.servers.is_empty());
+    }
+
+    #[test]
+    fn zero_and_empty_values_are_rejected() {
+        assert!(toml::from_str::<Config>("tick_rate = 0").is_err());
+        assert!(toml::from_str::<Config>("[[server]]\nurl = \" \"").is_err());
+        assert!(toml::from_str::<Config>("[[server]]\nurl = \"http://a\"\ntoken = \"\"").is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::server::{Server, ServerId};
+use crate::util::{format_endpoint, parse_endpoint};
+
+/// A line of an endpoint file. Everything that isn't an endpoint, such as comments, blank lines
+/// and lines we couldn't parse, is kept as it was written
+enum Line {
+    Text(String),
+    Endpoint(ServerId),
+}
+
+/// An endpoint file as it was loaded or last saved, so it can be written back
+/// with its comments where they were
+pub struct EndpointFile {
+    pub path: PathBuf,
+    lines: Vec<Line>,
+    /// Servers defined in the config file or another endpoint file, which are never written here
+    elsewhere: HashSet<ServerId>,
+}
+
+impl EndpointFile {
+    /// A file we haven't read, saving to it writes only the servers
+    pub fn empty(path: PathBuf) -> EndpointFile {
+        EndpointFile { path, lines: vec![], elsewhere: HashSet::new() }
+    }
+
+    /// Reads a server for each endpoint in the file. Lines that can't be parsed are reported and skipped
+    pub fn load(path: PathBuf) -> io::Result<(EndpointFile, Vec<Server>)> {
+        let contents = fs::read_to_string(&path)?;
+        let mut servers: Vec<Server> = vec![];
+        let mut lines: Vec<Line> = vec![];
+        for (number, line) in contents.lines().enumerate() {
+            match parse_endpoint(line) {
+                Ok(Some((endpoint, options))) => {
+                    let server = Server::new(endpoint, options);
+                    lines.push(Line::Endpoint(server.id));
+                    servers.push(server);
+                }
+                Ok(None) => lines.push(Line::Text(line.to_string())),
+                Err(err) => {
+                    eprintln!("{}:{}: {}", path.display(), number + 1, err);
+                    lines.push(Line::Text(line.to_string()));
+                }
+            }
+        }
+        Ok((EndpointFile { path, lines, elsewhere: HashSet::new() }, servers))
+    }
+
+    /// Keeps the servers out of the file
+    pub fn leave_out(&mut self, ids: impl IntoIterator<Item = ServerId>) {
+        self.elsewhere.extend(ids);
+    }
+
+    /// Lets a server that replaced another take over its line
+    pub fn replaced(&mut self, old: ServerId, new: ServerId) {
+        if self.elsewhere.contains(&old) {
+            self.elsewhere.insert(new);
+        }
+        for line in self.lines.iter_mut() {
+            if let Line::Endpoint(id) = line {
+                if *id == old {
+                    *id = new;
+                }
+            }
+        }
+    }
+
+    /// Writes the servers to the file. The servers that came from this file take the places of its
+    /// endpoint lines in their current order, so comments stay where they were. Removed servers
+    /// leave out their line and servers from anywhere else are added at the end
+    pub fn save(&mut self, servers: &[Server]) -> io::Result<()> {
+        let ours: HashSet<ServerId> = self.lines.iter()
+            .filter_map(|line| match line {
+                Line::Endpoint(id) => Some(*id),
+                Line::Text(_) => None,
+            })
+            .collect();
+        let mut in_order = servers.iter().filter(|server| ours.contains(&server.id));
+        let remaining: HashSet<ServerId> = servers.iter().map(|server| server.id).collect();
+
+        let mut lines: Vec<Line> = vec![];
+        for line in self.lines.drain(..) {
+            match line {
+                Line::Endpoint(id) if !remaining.contains(&id) => {}
+                Line::Endpoint(_) => {
+                    if let Some(server) = in_order.next() {
+                        lines.push(Line::Endpoint(server.id));
+                    }
+                }
+                text => lines.push(text),
+            }
+        }
+        lines.extend(servers.iter()
+            .filter(|server| !ours.contains(&server.id) && !self.elsewhere.contains(&server.id))
+            .map(|server| Line::Endpoint(server.id)));
+        self.lines = lines;
+
+        let mut contents = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Text(text) => contents.push_str(text),
+                Line::Endpoint(id) => {
+                    if let Some(server) = servers.iter().find(|server| server.id == *id) {
+                        contents.push_str(&format_endpoint(&server.endpoint, &server.options));
+                    }
+                }
+            }
+            contents.push('\n');
+        }
+        write_replacing(&self.path, &contents)
+    }
+}
+
+/// Writes to a temporary file next to the destination first, so the file is never left half written.
+/// Endpoint files can hold tokens, so the temporary file gets the permissions of the file it replaces,
+/// or is only readable by us for a new file, before anything is written to it
+fn write_replacing(path: &Path, contents: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let permissions = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => private_permissions(),
+        Err(err) => return Err(err),
+    };
+    let mut file = fs::File::create(&temporary)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(contents.as_bytes())?;
+    drop(file);
+    fs::rename(&temporary, path)
+}
+
+#[cfg(unix)]
+fn private_permissions() -> Option<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn private_permissions() -> Option<fs::Permissions> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path in a directory of its own under the system's temporary directory
+    fn temporary_path(test: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("dashboard-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory.join("endpoints")
+    }
+
+    #[cfg(unix)]
+    fn mode(path: &Path) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_keeps_the_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temporary_path("permissions");
+        fs::write(&path, "# servers\nhttp://web-1:3000/resources token=secret\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let (mut file, servers) = EndpointFile::load(path.clone()).unwrap();
+        file.save(&servers).unwrap();
+        assert_eq!(mode(&path), 0o640);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# servers\nhttp://web-1:3000/resources token=secret\n");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn servers_of_other_files_are_left_out() {
+        let path = temporary_path("other-files");
+        fs::write(&path, "# first\nhttp://web-1:3000/resources\n").unwrap();
+        let other_path = path.with_file_name("other");
+        fs::write(&other_path, "http://db-1:3000/resources\n").unwrap();
+
+        let (mut file, mut servers) = EndpointFile::load(path.clone()).unwrap();
+        let (_, other_servers) = EndpointFile::load(other_path).unwrap();
+        file.leave_out(other_servers.iter().map(|server| server.id));
+        servers.extend(other_servers);
+        servers.push(Server::new(String::from("http://web-2:3000/resources"), Default::default()));
+        file.save(&servers).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "# first\nhttp://web-1:3000/resources\nhttp://web-2:3000/resources\n");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_files_are_private() {
+        let path = temporary_path("new");
+        let server = Server::new(String::from("http://web-1:3000/resources"), Default::default());
+        EndpointFile::empty(path.clone()).save(&[server]).unwrap();
+        assert_eq!(mode(&path), 0o600);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+use reqwest::StatusCode;
+
+/// Consecutive failures after which a server is shown as offline rather than stale
+const OFFLINE_AFTER_FAILURES: u32 = 3;
+
+/// A server that hasn't answered for this many update intervals is stale
+const STALE_AFTER_INTERVALS: u32 = 3;
+
+/// Why a request to an agent failed
+#[derive(Clone, Debug, PartialEq)]
+pub enum FetchError {
+    Timeout,
+    Refused,
+    /// Any other failure to connect, such as DNS or TLS errors, or a dropped connection
+    Connection(String),
+    Status(StatusCode),
+    /// The agent answered with something that isn't the JSON we expect
+    Parse(String),
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return FetchError::Timeout;
+        }
+        let cause = match io_cause(&err) {
+            Ok(error) => return error,
+            Err(cause) => cause,
+        };
+        match err.status() {
+            Some(status) => FetchError::Status(status),
+            None if err.is_decode() => FetchError::Parse(cause),
+            None => FetchError::Connection(cause),
+        }
+    }
+}
+
+/// The errors of requests over an agent's Unix socket
+impl From<hyper::Error> for FetchError {
+    fn from(err: hyper::Error) -> Self {
+        if err.is_timeout() {
+            return FetchError::Timeout;
+        }
+        io_cause(&err).unwrap_or_else(FetchError::Connection)
+    }
+}
+
+/// The reason is buried in the sources reqwest and hyper wrap around the io error. Gives the
+/// innermost cause when it isn't a refused or timed out connection
+fn io_cause(err: &(dyn Error + 'static)) -> Result<FetchError, String> {
+    let mut cause = err;
+    while let Some(source) = cause.source() {
+        if let Some(io_error) = source.downcast_ref::<io::Error>() {
+            match io_error.kind() {
+                io::ErrorKind::ConnectionRefused => return Ok(FetchError::Refused),
+                io::ErrorKind::TimedOut => return Ok(FetchError::Timeout),
+                _ => {}
+            }
+        }
+        cause = source;
+    }
+    Err(cause.to_string())
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Timeout => write!(f, "timed out"),
+            FetchError::Refused => write!(f, "connection refused"),
+            FetchError::Connection(reason) => write!(f, "connection failed: {}", reason),
+            FetchError::Status(status) => write!(f, "HTTP {}", status),
+            FetchError::Parse(reason) => write!(f, "invalid response: {}", reason),
+        }
+    }
+}
+
+/// How the connection to a server's agent is doing, derived from its Health
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionStatus {
+    /// Nothing has been heard from the agent yet
+    Connecting,
+    Online,
+    /// The last request failed or the agent hasn't answered for a while, the data is out of date
+    Stale,
+    Offline,
+}
+
+impl fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let title = match self {
+            ConnectionStatus::Connecting => "CONNECTING",
+            ConnectionStatus::Online => "ONLINE",
+            ConnectionStatus::Stale => "STALE",
+            ConnectionStatus::Offline => "OFFLINE",
+        };
+        write!(f, "{}", title)
+    }
+}
+
+/// The state of the connection to a server's agent
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+    pub last_success: Option<Instant>,
+    pub last_error: Option<FetchError>,
+    pub consecutive_failures: u32,
+    /// How long the agent took to answer the last successful request
+    pub latency: Option<Duration>,
+}
+
+impl Health {
+    /// Records an answer from the agent. Updates pushed over a stream have no latency of their
+    /// own, so the one measured when the stream was opened is kept
+    pub fn succeeded(&mut self, latency: Option<Duration>) {
+        self.last_success = Some(Instant::now());
+        self.consecutive_failures = 0;
+        if latency.is_some() {
+            self.latency = latency;
+        }
+    }
+
+    /// The last error is kept after the agent answers again, so it can still be looked up
+    pub fn failed(&mut self, error: FetchError) {
+        self.last_error = Some(error);
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+    }
+
+    /// How the connection is doing, for a server that should answer every update interval
+    pub fn status(&self, update_interval: Duration) -> ConnectionStatus {
+        match self.last_success {
+            None if self.last_error.is_none() => ConnectionStatus::Connecting,
+            None => ConnectionStatus::Offline,
+            Some(_) if self.consecutive_failures >= OFFLINE_AFTER_FAILURES => ConnectionStatus::Offline,
+            Some(_) if self.consecutive_failures > 0 => ConnectionStatus::Stale,
+            Some(last_success) if last_success.elapsed() > update_interval * STALE_AFTER_INTERVALS => {
+                ConnectionStatus::Stale
+            }
+            Some(_) => ConnectionStatus::Online,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answered(ago: Duration) -> Health {
+        Health { last_success: Some(Instant::now() - ago), ..Default::default() }
+    }
+
+    #[test]
+    fn stale_after_missing_a_few_intervals() {
+        let health = answered(Duration::from_secs(10));
+        assert_eq!(health.status(Duration::from_secs(1)), ConnectionStatus::Stale);
+        // A server polled every minute isn't late after ten seconds
+        assert_eq!(health.status(Duration::from_secs(60)), ConnectionStatus::Online);
+    }
+
+    #[test]
+    fn failures_count_before_the_interval() {
+        let mut health = answered(Duration::ZERO);
+        health.failed(FetchError::Timeout);
+        assert_eq!(health.status(Duration::from_secs(60)), ConnectionStatus::Stale);
+        for _ in 1..OFFLINE_AFTER_FAILURES {
+            health.failed(FetchError::Refused);
+        }
+        assert_eq!(health.status(Duration::from_secs(60)), ConnectionStatus::Offline);
+        health.succeeded(None);
+        assert_eq!(health.status(Duration::from_secs(60)), ConnectionStatus::Online);
+    }
+
+    #[test]
+    fn connecting_until_the_first_answer_or_error() {
+        let mut health = Health::default();
+        assert_eq!(health.status(Duration::from_secs(1)), ConnectionStatus::Connecting);
+        health.failed(FetchError::Refused);
+        assert_eq!(health.status(Duration::from_secs(1)), ConnectionStatus::Offline);
+    }
+}
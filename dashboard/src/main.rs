@@ -4,27 +4,77 @@ mod ui;
 mod app;
 mod util;
 mod args;
+mod health;
+mod endpoint_file;
+mod config;
+mod theme;
 
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use anyhow::{Result};
 use clap::Parser;
 use tokio::sync::Mutex;
-use crate::args::Args;
-use crate::server::{refresh_servers};
-use crate::server::init_with_endpoint;
+use crate::app::Appearance;
+use crate::args::{Args, DEFAULT_CHART_WINDOW};
+use crate::config::Config;
+use crate::endpoint_file::EndpointFile;
+use schema::ProcessSort;
+use crate::server::{refresh_servers, Clients, FetchSettings, ServerId};
 use crate::terminal::{run};
-use crate::util::extract_endpoints_from_files;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse(); //Parse command line arguments
-    let server_endpoints = extract_endpoints_from_files(args.files);
+    let config = Config::load(args.config.as_deref()).map_err(anyhow::Error::msg)?;
+    let tick_rate = config.tick_rate(&args);
+    let update_frequency = config.update_frequency(&args);
+    let appearance = Appearance {
+        theme: config.theme,
+        units: config.units,
+        chart_window: Duration::from_secs(config.chart_window.unwrap_or(DEFAULT_CHART_WINDOW)),
+    };
+
+    // The servers of the config file come first, followed by those of the endpoint files
+    let mut loaded_servers: Vec<_> = config.servers.into_iter().map(|server| server.into_server()).collect();
+    let config_servers: Vec<_> = loaded_servers.iter().map(|server| server.id).collect();
+    // Each endpoint file we loaded with the servers read from it
+    let mut endpoint_files: Vec<(EndpointFile, Vec<ServerId>)> = vec![];
+    for file_path in args.files {
+        match EndpointFile::load(PathBuf::from(&file_path)) {
+            Ok((file, servers)) => {
+                endpoint_files.push((file, servers.iter().map(|server| server.id).collect()));
+                loaded_servers.extend(servers);
+            }
+            Err(_) => eprintln!("Failed to open file: {}", file_path),
+        }
+    }
+    // Save to the chosen path, keeping its comments if it's one of the files we loaded. The servers
+    // of the other files we loaded belong to those, so they aren't copied into it
+    let chosen = match &args.save_to {
+        Some(path) => endpoint_files.iter().position(|(file, _)| file.path == *path),
+        None if !endpoint_files.is_empty() => Some(0),
+        None => None,
+    };
+    let mut endpoint_file = match chosen {
+        Some(index) => {
+            let (mut file, _) = endpoint_files.swap_remove(index);
+            file.leave_out(endpoint_files.into_iter().flat_map(|(_, ids)| ids));
+            Some(file)
+        }
+        None => args.save_to.map(EndpointFile::empty),
+    };
+    if let Some(file) = endpoint_file.as_mut() {
+        file.leave_out(config_servers);
+    }
+    // Fail before the terminal is taken over if the certificates can't be read
+    let clients = Clients::new(args.ca_cert.as_deref(), args.client_cert.as_deref(), args.client_key.as_deref())?;
 
     // Initiate an instance of Server for each endpoint.
     // Arc to ensure that both threads can share ownership of the mutex
     // And the mutex ensures the data is protected from concurrent access
-    let servers = Arc::new(Mutex::new(init_with_endpoint(server_endpoints.clone())));
+    let servers = Arc::new(Mutex::new(loaded_servers));
 
     // Create an atomic bool wrapped in an Arc to pass to the refresh_thread
     let exit_loop = Arc::new(AtomicBool::new(false));
@@ -32,13 +82,26 @@ async fn main() -> Result<()> {
 
     // Spawn the refresh function as an asynchronous task so we can concurrently render the UI
     // While updating servers 'in the background'
+    // The process table sort order is chosen in the UI but needed by the refresh task
+    // to request the top processes in the same order
+    let process_sort = Arc::new(RwLock::new(ProcessSort::default()));
+    let process_sort_clone = Arc::clone(&process_sort);
+
+    let settings = FetchSettings {
+        update_frequency: Duration::from_millis(update_frequency),
+        history_window: appearance.chart_window,
+        mode: args.mode,
+        process_limit: args.process_limit,
+        concurrency: args.concurrency,
+        request_timeout: Duration::from_millis(args.timeout),
+    };
     let exit_loop_clone = Arc::clone(&exit_loop);
     tokio::spawn(async move {
-        refresh_servers(servers_clone, args.update_frequency, exit_loop_clone, server_endpoints).await;
+        refresh_servers(servers_clone, clients, exit_loop_clone, process_sort_clone, settings).await;
     });
 
     // Set up the terminal and run our TUI loop
-    run(Arc::clone(&servers), args.tick_rate, args.update_frequency).await
+    run(Arc::clone(&servers), tick_rate, update_frequency, process_sort, appearance, endpoint_file, args.autosave).await
         .expect("Application loop failure");
 
     //Shut down the refresh thread by altering the AtomicBool value
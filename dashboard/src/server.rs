@@ -1,91 +1,696 @@
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
-use reqwest::{Client, StatusCode};
-use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
-use tokio::time::sleep;
-
-#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use hyper::body::{Bytes, HttpBody};
+use hyperlocal::UnixConnector;
+use reqwest::{Certificate, Client, Identity, StatusCode};
+use reqwest::header::{ACCEPT, AUTHORIZATION};
+use schema::{HistorySample, Process, ProcessSort, Resources};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use crate::health::{FetchError, Health};
+use crate::util::endpoint_route;
+
+/// The agent sends a keep-alive every 15 seconds, a stream silent for longer than this is dead
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the dashboard gets new data from the agents
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum FetchMode {
+    /// Request the resources from every endpoint on each update
+    Poll,
+    /// Keep a stream open to every endpoint and let the agent push each refresh.
+    /// Agents that don't support streaming are polled
+    Stream,
+}
+
+/// Settings of an endpoint, given after its URL in an endpoint file or in its table in the config file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EndpointOptions {
+    /// Sent as a bearer token in the Authorization header of every request to the agent
+    pub token: Option<String>,
+    /// Skip verifying the agent's certificate, for test setups with self-signed certificates
+    pub insecure: bool,
+    /// Shown instead of the hostname the agent reports
+    pub name: Option<String>,
+    /// Labels shown next to the name, e.g. the environment or role of the server
+    pub tags: Vec<String>,
+    /// How long a request to this endpoint may take, instead of --timeout
+    pub timeout: Option<Duration>,
+    /// How often this endpoint is polled, instead of the update frequency. Agents that
+    /// stream push their updates at their own pace
+    pub poll_interval: Option<Duration>,
+}
+
+/// How and how often the refresher fetches data, taken from the command line and the config file
+#[derive(Clone, Copy, Debug)]
+pub struct FetchSettings {
+    pub update_frequency: Duration,
+    /// How far back we ask for history, as far as the charts go
+    pub history_window: Duration,
+    pub mode: FetchMode,
+    /// How many processes to ask each agent for
+    pub process_limit: usize,
+    /// How many endpoints are polled at the same time
+    pub concurrency: usize,
+    /// How long a single request may take before we give up on it
+    pub request_timeout: Duration,
+}
+
+/// Identifies a server for as long as the dashboard runs. Unlike its index it doesn't change
+/// when other servers are added, removed or reordered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ServerId(u64);
+
+impl ServerId {
+    fn next() -> ServerId {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        ServerId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A server we monitor, holding the latest data fetched from its agent
+#[derive(Clone, Debug, Default)]
 pub struct Server {
-    #[serde(skip)]
+    pub id: ServerId,
     pub endpoint: String,
-    pub hostname: String,
-    pub total_memory: u64,
-    pub used_memory: u64,
-    pub total_space: u64,
-    pub available_space: u64,
-    pub cpu_amount: usize,
-    pub cpu_usage: f32,
-    pub cpu_load_per_core: Vec<f32>,
-    pub disk_names: Vec<String>,
-    pub disk_available: Vec<u64>,
-    pub disk_total: Vec<u64>,
-    pub uptime: u64,
-    pub os_version: String,
-    pub kernel_version: String,
-    pub load_avg_one: f64,
-    pub load_avg_five: f64,
-    pub load_avg_fifteen: f64,
-    pub bytes_received: u64,
-    pub bytes_transmitted: u64,
+    pub options: EndpointOptions,
+    pub resources: Resources,
+    pub processes: Vec<Process>,
+    /// Only set on the first response, so the charts can be filled with what the agent recorded
+    pub history: Vec<HistorySample>,
+    pub health: Health,
 }
 
 impl Server{
-    pub fn new(endpoint: String) -> Server {
+    pub fn new(endpoint: String, options: EndpointOptions) -> Server {
         Server {
+            id: ServerId::next(),
             endpoint,
+            options,
             ..Default::default()
         }
     }
+
+    /// The name given in the config, the hostname, or the endpoint until the agent has told us its hostname
+    pub fn name(&self) -> &str {
+        if let Some(name) = &self.options.name {
+            name
+        } else if self.resources.hostname.is_empty() {
+            &self.endpoint
+        } else {
+            &self.resources.hostname
+        }
+    }
+}
+
+/// The HTTP clients requests are made with. Both trust the given CAs and present the client
+/// certificate, but the insecure one doesn't verify the agent's certificate.
+/// Agents listening on a Unix socket, given as `unix:///path/to.sock`, are reached with the unix client
+#[derive(Clone)]
+pub struct Clients {
+    client: Client,
+    insecure: Client,
+    unix: hyper::Client<UnixConnector>,
+}
+
+impl Clients {
+    /// Reads the CA bundle and the client certificate, if given, and builds the clients with them
+    pub fn new(ca_cert: Option<&Path>, client_cert: Option<&Path>, client_key: Option<&Path>) -> Result<Clients> {
+        let ca_certs = match ca_cert {
+            Some(path) => {
+                let pem = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+                Certificate::from_pem_bundle(&pem)
+                    .with_context(|| format!("Invalid CA bundle {}", path.display()))?
+            }
+            None => vec![],
+        };
+        let identity = match (client_cert, client_key) {
+            (Some(cert), Some(key)) => {
+                let mut pem = fs::read(cert).with_context(|| format!("Failed to read {}", cert.display()))?;
+                pem.extend(fs::read(key).with_context(|| format!("Failed to read {}", key.display()))?);
+                Some(Identity::from_pem(&pem).context("Invalid client certificate or key")?)
+            }
+            _ => None,
+        };
+
+        let build = |insecure: bool| {
+            let mut builder = Client::builder()
+                .use_rustls_tls()
+                .connect_timeout(Duration::from_secs(2))
+                .danger_accept_invalid_certs(insecure);
+            for cert in &ca_certs {
+                builder = builder.add_root_certificate(cert.clone());
+            }
+            if let Some(identity) = &identity {
+                builder = builder.identity(identity.clone());
+            }
+            builder.build().context("Failed to set up the HTTP client")
+        };
+        let unix = hyper::Client::builder().build(UnixConnector);
+        Ok(Clients { client: build(false)?, insecure: build(true)?, unix })
+    }
+
+    /// Sends a GET request for one of the agent's routes, carrying the endpoint's token if it has one.
+    /// HTTP endpoints are the URL of the resources with the other routes next to it, while
+    /// `unix://` endpoints are the path of the socket
+    async fn get(&self, endpoint: &str, route: &str, query: &[(&str, &str)], options: &EndpointOptions,
+                 accept: Option<&str>) -> Result<Response, FetchError> {
+        if let Some(socket) = endpoint.strip_prefix("unix://") {
+            let mut path = format!("/{}", route);
+            if !query.is_empty() {
+                let pairs: Vec<String> = query.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+                path = format!("{}?{}", path, pairs.join("&"));
+            }
+            let mut request = hyper::Request::get(hyper::Uri::from(hyperlocal::Uri::new(socket, &path)));
+            if let Some(token) = &options.token {
+                request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+            }
+            if let Some(accept) = accept {
+                request = request.header(ACCEPT, accept);
+            }
+            let request = request.body(hyper::Body::empty())
+                .map_err(|err| FetchError::Connection(err.to_string()))?;
+            return Ok(Response::Unix(self.unix.request(request).await?));
+        }
+
+        let client = if options.insecure { &self.insecure } else { &self.client };
+        let url = match route {
+            "resources" => endpoint.to_string(),
+            _ => endpoint_route(endpoint, route),
+        };
+        let mut request = client.get(url).query(query);
+        if let Some(token) = &options.token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(accept) = accept {
+            request = request.header(ACCEPT, accept);
+        }
+        Ok(Response::Http(request.send().await?))
+    }
+}
+
+/// A response from an agent, over HTTP(S) or its Unix socket
+enum Response {
+    Http(reqwest::Response),
+    Unix(hyper::Response<hyper::Body>),
 }
 
-/// Initialises a Vec<Server> with default values and an endpoint for each instance of Server
-pub fn init_with_endpoint(endpoints: Vec<String>) -> Vec<Server> {
-    let mut servers: Vec<Server> = vec![];
-    for endpoint in endpoints {
-        servers.push(Server::new(endpoint))
+impl Response {
+    fn status(&self) -> StatusCode {
+        match self {
+            Response::Http(response) => response.status(),
+            Response::Unix(response) => response.status(),
+        }
+    }
+
+    /// The next part of the body, None once all of it has been read
+    async fn chunk(&mut self) -> Result<Option<Bytes>, FetchError> {
+        match self {
+            Response::Http(response) => Ok(response.chunk().await?),
+            Response::Unix(response) => Ok(response.body_mut().data().await.transpose()?),
+        }
+    }
+
+    async fn text(self) -> Result<String, FetchError> {
+        match self {
+            Response::Http(response) => Ok(response.text().await?),
+            Response::Unix(response) => {
+                let body = hyper::body::to_bytes(response.into_body()).await?;
+                Ok(String::from_utf8_lossy(&body).into_owned())
+            }
+        }
     }
-    servers
 }
 
-/// Iterates through the vector of Server and makes a GET request to each endpoint
-/// and updates the struct if we got a status code 200 in the response.
-/// Otherwise we silently fail
-async fn get_servers(servers: &mut [Server], client: &Client){
-    for server in servers.iter_mut() {
-        let endpoint = server.endpoint.clone();
-        if let Ok(response) = client.get(&endpoint)
-            .timeout(Duration::from_secs(2))
-            .send().await {
-            if response.status() == StatusCode::OK {
-                let body = response.text().await.unwrap();
-                if let Ok(deserialized_server) = serde_json::from_str(&body) {
-                    *server = deserialized_server;
-                    server.endpoint = endpoint;
+/// Fetches a route that isn't needed for the server to be shown, as JSON.
+/// Agents without the route, or that don't answer in time, simply give us the default
+async fn get_optional<T: serde::de::DeserializeOwned + Default>(clients: &Clients, endpoint: &str, route: &str,
+                                                                query: &[(&str, &str)], options: &EndpointOptions,
+                                                                request_timeout: Duration) -> T {
+    let request = async {
+        let response = clients.get(endpoint, route, query, options, None).await.ok()?;
+        if response.status() != StatusCode::OK {
+            return None;
+        }
+        serde_json::from_str(&response.text().await.ok()?).ok()
+    };
+    timeout(request_timeout, request).await.ok().flatten().unwrap_or_default()
+}
+
+/// Fetches the top processes from the agent's /processes route
+async fn get_processes(endpoint: &str, options: &EndpointOptions, clients: &Clients, request_timeout: Duration,
+                       process_sort: ProcessSort, process_limit: usize) -> Vec<Process> {
+    let limit = process_limit.to_string();
+    let query = [("sort", process_sort.as_str()), ("limit", limit.as_str())];
+    get_optional(clients, endpoint, "processes", &query, options, request_timeout).await
+}
+
+/// Fetches the samples of the last history_window from the agent's /history route
+async fn get_history(endpoint: &str, options: &EndpointOptions, clients: &Clients,
+                     request_timeout: Duration, history_window: Duration) -> Vec<HistorySample> {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+        .saturating_sub(history_window.as_millis() as u64)
+        .to_string();
+    let query = [("since", since.as_str()),
+                 ("fields", "cpu_usage,used_memory,total_memory,bytes_received,bytes_transmitted")];
+    get_optional(clients, endpoint, "history", &query, options, request_timeout).await
+}
+
+/// What the refresher needs to know about a server to fetch its data
+#[derive(Clone, Debug, PartialEq)]
+struct Target {
+    id: ServerId,
+    endpoint: String,
+    options: EndpointOptions,
+}
+
+/// Everything needed to fetch data from an endpoint and store it,
+/// cheap to clone into the task of each stream and poll
+#[derive(Clone)]
+struct Fetcher {
+    clients: Clients,
+    servers: Arc<Mutex<Vec<Server>>>,
+    process_sort: Arc<RwLock<ProcessSort>>,
+    process_limit: usize,
+    /// How long a single request may take before we give up on it, unless the endpoint has its own timeout
+    request_timeout: Duration,
+    history_window: Duration,
+    /// Limits how many endpoints are polled at the same time
+    poll_permits: Arc<Semaphore>,
+}
+
+/// Why a stream task stopped
+enum StreamEnd {
+    /// The agent has no /stream route, so we poll it instead
+    Unsupported,
+    Disconnected,
+}
+
+impl Fetcher {
+    /// Makes a GET request to the endpoint and updates the server if we got a status code 200
+    /// in the response. Otherwise the failure is recorded in the server's health
+    async fn poll(&self, target: &Target) {
+        // The semaphore is never closed
+        let _permit = self.poll_permits.acquire().await.unwrap();
+        let started = Instant::now();
+        match self.get_resources(&target.endpoint, &target.options).await {
+            Ok(update) => self.apply(target, update, Some(started.elapsed())).await,
+            Err(err) => self.record_failure(target.id, err).await,
+        }
+    }
+
+    fn request_timeout(&self, options: &EndpointOptions) -> Duration {
+        options.timeout.unwrap_or(self.request_timeout)
+    }
+
+    async fn get_resources(&self, endpoint: &str, options: &EndpointOptions) -> Result<Resources, FetchError> {
+        let request = async {
+            let response = self.clients.get(endpoint, "resources", &[], options, None).await?;
+            if response.status() != StatusCode::OK {
+                return Err(FetchError::Status(response.status()));
+            }
+            let body = response.text().await?;
+            serde_json::from_str(&body).map_err(|err| FetchError::Parse(err.to_string()))
+        };
+        timeout(self.request_timeout(options), request).await.unwrap_or(Err(FetchError::Timeout))
+    }
+
+    /// Keeps a Server-Sent Events stream open to the agent's /stream route and applies every
+    /// update it pushes, until the connection is lost
+    async fn stream(self, target: Target) -> StreamEnd {
+        let started = Instant::now();
+        let request = self.clients.get(&target.endpoint, "stream", &[], &target.options, Some("text/event-stream"));
+        let mut response = match timeout(self.request_timeout(&target.options), request).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) => {
+                self.record_failure(target.id, err).await;
+                return StreamEnd::Disconnected;
+            }
+            Err(_) => {
+                self.record_failure(target.id, FetchError::Timeout).await;
+                return StreamEnd::Disconnected;
+            }
+        };
+        if response.status() == StatusCode::NOT_FOUND {
+            return StreamEnd::Unsupported;
+        }
+        if response.status() != StatusCode::OK {
+            self.record_failure(target.id, FetchError::Status(response.status())).await;
+            return StreamEnd::Disconnected;
+        }
+        let latency = started.elapsed();
+
+        let mut buffer: Vec<u8> = vec![];
+        loop {
+            // The agent sends keep-alive comments, so a silent connection is a dead one
+            let chunk = match timeout(STREAM_IDLE_TIMEOUT, response.chunk()).await {
+                Ok(Ok(Some(chunk))) => chunk,
+                Ok(Ok(None)) => {
+                    self.record_failure(target.id, FetchError::Connection("stream closed by the agent".to_string())).await;
+                    return StreamEnd::Disconnected;
+                }
+                Ok(Err(err)) => {
+                    self.record_failure(target.id, err).await;
+                    return StreamEnd::Disconnected;
+                }
+                Err(_) => {
+                    self.record_failure(target.id, FetchError::Timeout).await;
+                    return StreamEnd::Disconnected;
+                }
+            };
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+                let event: Vec<u8> = buffer.drain(..end + 2).collect();
+                if let Some(data) = event_data(&String::from_utf8_lossy(&event)) {
+                    match serde_json::from_str(&data) {
+                        Ok(update) => self.apply(&target, update, Some(latency)).await,
+                        Err(err) => self.record_failure(target.id, FetchError::Parse(err.to_string())).await,
+                    }
                 }
             }
         }
     }
+
+    /// Fetches what isn't part of the resources, the processes and the history the first time
+    /// a server answers, before storing the update in the server. A server removed in the meantime
+    /// is simply not found
+    async fn apply(&self, target: &Target, resources: Resources, latency: Option<Duration>) {
+        let (endpoint, options) = (&target.endpoint, &target.options);
+        let process_sort = *self.process_sort.read().unwrap();
+        let request_timeout = self.request_timeout(options);
+        let processes = get_processes(endpoint, options, &self.clients, request_timeout, process_sort, self.process_limit).await;
+
+        // A server that has never answered before has no hostname yet
+        let first_response = self.servers.lock().await
+            .iter()
+            .any(|server| server.id == target.id && server.resources.hostname.is_empty());
+        let history = if first_response {
+            get_history(endpoint, options, &self.clients, request_timeout, self.history_window).await
+        } else {
+            vec![]
+        };
+
+        if let Some(server) = self.servers.lock().await.iter_mut().find(|server| server.id == target.id) {
+            server.resources = resources;
+            server.processes = processes;
+            server.history = history;
+            server.health.succeeded(latency);
+        }
+    }
+
+    async fn record_failure(&self, id: ServerId, error: FetchError) {
+        if let Some(server) = self.servers.lock().await.iter_mut().find(|server| server.id == id) {
+            server.health.failed(error);
+        }
+    }
+}
+
+/// Joins the data lines of a Server-Sent Event. Events without data, such as keep-alive
+/// comments, give us None
+fn event_data(event: &str) -> Option<String> {
+    let data: Vec<&str> = event.lines()
+        .filter_map(|line| line.trim_end_matches('\r').strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect();
+    if data.is_empty() {
+        None
+    } else {
+        Some(data.join("\n"))
+    }
 }
 
-/// Creates a Client to make requests with, updates a vector of Servers and once done,
-/// we lock the mutex and update the data
-pub async fn refresh_servers(servers: Arc<Mutex<Vec<Server>>>, update_frequency: u64,
-                             exit_loop: Arc<AtomicBool>, endpoints: Vec<String>){
-    let client = Client::new();
-    let mut servers_container = init_with_endpoint(endpoints);
+/// Polls each endpoint in a task of its own, so every response is applied as soon as it arrives
+/// and a slow endpoint doesn't hold up the others. An endpoint whose previous poll hasn't
+/// finished yet is skipped rather than piling up requests to a host that isn't answering,
+/// and so is one polled less often than the update frequency that isn't due yet
+fn get_servers(fetcher: &Fetcher, targets: &[Target], polls: &mut HashMap<ServerId, JoinHandle<()>>,
+               last_polled: &mut HashMap<ServerId, Instant>, update_frequency: Duration) {
+    polls.retain(|_, poll| !poll.is_finished());
+    for target in targets {
+        if polls.contains_key(&target.id) {
+            continue;
+        }
+        let interval = target.options.poll_interval.unwrap_or(update_frequency);
+        if last_polled.get(&target.id).is_some_and(|polled| polled.elapsed() < interval) {
+            continue;
+        }
+        last_polled.insert(target.id, Instant::now());
+        let fetcher = fetcher.clone();
+        let target = target.clone();
+        polls.insert(target.id, tokio::spawn(async move {
+            fetcher.poll(&target).await;
+        }));
+    }
+}
+
+/// Keeps the servers up to date until we exit.
+/// Endpoints are read from the shared servers on every update, so endpoints added from the UI
+/// are picked up. In stream mode each endpoint gets a task holding a stream open, and endpoints
+/// whose agent doesn't support streaming are polled instead
+pub async fn refresh_servers(servers: Arc<Mutex<Vec<Server>>>, clients: Clients, exit_loop: Arc<AtomicBool>,
+                             process_sort: Arc<RwLock<ProcessSort>>, settings: FetchSettings){
+    let mode = settings.mode;
+    let fetcher = Fetcher {
+        clients,
+        servers,
+        process_sort,
+        process_limit: settings.process_limit,
+        request_timeout: settings.request_timeout,
+        history_window: settings.history_window,
+        poll_permits: Arc::new(Semaphore::new(settings.concurrency.max(1))),
+    };
+    // Streams are opened with the endpoint and options of their server and reopened if those are edited
+    let mut streams: HashMap<ServerId, (Target, JoinHandle<StreamEnd>)> = HashMap::new();
+    let mut polls: HashMap<ServerId, JoinHandle<()>> = HashMap::new();
+    let mut polling_only: HashSet<ServerId> = HashSet::new();
+    let mut last_polled: HashMap<ServerId, Instant> = HashMap::new();
+    // Wakes up often enough for the endpoint polled most often
+    let mut tick = settings.update_frequency;
+
     while !exit_loop.load(Ordering::Relaxed) {
-        sleep(Duration::from_millis(update_frequency)).await;
-        get_servers(&mut servers_container, &client).await;
+        sleep(tick).await;
+        let targets: Vec<Target> = fetcher.servers.lock().await
+            .iter()
+            .map(|server| Target {
+                id: server.id,
+                endpoint: server.endpoint.clone(),
+                options: server.options.clone(),
+            })
+            .collect();
+
+        // Close the streams and cancel the polls of servers that are gone or were edited
+        let known = |id: &ServerId| targets.iter().any(|target| target.id == *id);
+        streams.retain(|_, (target, stream)| {
+            let keep = targets.contains(target);
+            if !keep {
+                stream.abort();
+            }
+            keep
+        });
+        polls.retain(|id, poll| {
+            let keep = known(id);
+            if !keep {
+                poll.abort();
+            }
+            keep
+        });
+        polling_only.retain(known);
+        last_polled.retain(|id, _| known(id));
+        tick = targets.iter()
+            .filter_map(|target| target.options.poll_interval)
+            .fold(settings.update_frequency, Duration::min);
 
-        //Only lock and update mutex after we've fetched data
-        let mut servers_data = servers.lock().await;
+        let mut to_poll: Vec<Target> = vec![];
+        for target in &targets {
+            if mode == FetchMode::Poll || polling_only.contains(&target.id) {
+                to_poll.push(target.clone());
+                continue;
+            }
+            match streams.get(&target.id) {
+                Some((_, stream)) if !stream.is_finished() => continue,
+                Some(_) => {
+                    let (_, stream) = streams.remove(&target.id).unwrap();
+                    if let Ok(StreamEnd::Unsupported) = stream.await {
+                        polling_only.insert(target.id);
+                        to_poll.push(target.clone());
+                        continue;
+                    }
+                }
+                None => {}
+            }
+            // (Re)connect, the agent sends its current resources as soon as we're subscribed
+            let stream = tokio::spawn(fetcher.clone().stream(target.clone()));
+            streams.insert(target.id, (target.clone(), stream));
+        }
+        get_servers(&fetcher, &to_poll, &mut polls, &mut last_polled, settings.update_frequency);
+    }
+
+    for (_, stream) in streams.values() {
+        stream.abort();
+    }
+    for poll in polls.values() {
+        poll.abort();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// How many requests for the resources the mock agents are answering at the moment, and the most at once
+    #[derive(Default)]
+    pub(crate) struct InFlight {
+        current: AtomicUsize,
+        most: AtomicUsize,
+    }
 
-        //If we've added a new endpoint from our UI thread we need to avoid overwriting it
-        if servers_data.len() != servers_container.len(){
-            servers_container.push(servers_data.last().unwrap().clone());
+    /// Starts an agent on a local port that answers requests for its resources and every other
+    /// route with a 404, the slow route only after the delay. Returns the URL of its resources
+    pub(crate) async fn mock_agent(hostname: &'static str, slow_route: &'static str, delay: Duration,
+                                   in_flight: Arc<InFlight>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let in_flight = in_flight.clone();
+                tokio::spawn(async move {
+                    let mut request = vec![];
+                    let mut buffer = [0; 1024];
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match socket.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buffer[..read]),
+                        }
+                    }
+                    let resources = request.starts_with(b"GET /resources ");
+                    if resources {
+                        let current = in_flight.current.fetch_add(1, Ordering::SeqCst) + 1;
+                        in_flight.most.fetch_max(current, Ordering::SeqCst);
+                    }
+                    if request.starts_with(format!("GET /{}", slow_route).as_bytes()) {
+                        sleep(delay).await;
+                    }
+                    let response = if resources {
+                        in_flight.current.fetch_sub(1, Ordering::SeqCst);
+                        let body = serde_json::to_string(&Resources { hostname: hostname.to_string(), ..Default::default() }).unwrap();
+                        format!("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                                body.len(), body)
+                    } else {
+                        "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string()
+                    };
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}/resources", address)
+    }
+
+    fn fetcher(servers: Vec<Server>, concurrency: usize, request_timeout: Duration) -> Fetcher {
+        Fetcher {
+            clients: Clients::new(None, None, None).unwrap(),
+            servers: Arc::new(Mutex::new(servers)),
+            process_sort: Arc::new(RwLock::new(ProcessSort::Cpu)),
+            process_limit: 10,
+            request_timeout,
+            history_window: Duration::from_secs(60),
+            poll_permits: Arc::new(Semaphore::new(concurrency)),
         }
-        *servers_data = servers_container.clone();
     }
-}
\ No newline at end of file
+
+    /// Fetches the processes of the server and stores the resources in it in a task of its own,
+    /// the way a poll or stream does once the agent has answered
+    pub(crate) fn apply_in_background(servers: Arc<Mutex<Vec<Server>>>, server: &Server, resources: Resources) -> JoinHandle<()> {
+        let fetcher = Fetcher { servers, ..fetcher(vec![], 1, Duration::from_secs(5)) };
+        let target = Target { id: server.id, endpoint: server.endpoint.clone(), options: server.options.clone() };
+        tokio::spawn(async move {
+            fetcher.apply(&target, resources, None).await;
+        })
+    }
+
+    async fn targets(fetcher: &Fetcher) -> Vec<Target> {
+        fetcher.servers.lock().await
+            .iter()
+            .map(|server| Target { id: server.id, endpoint: server.endpoint.clone(), options: server.options.clone() })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn slow_endpoint_does_not_hold_up_fast_one() {
+        let in_flight = Arc::new(InFlight::default());
+        let slow = mock_agent("slow", "resources", Duration::from_secs(10), in_flight.clone()).await;
+        let fast = mock_agent("fast", "resources", Duration::ZERO, in_flight).await;
+        let request_timeout = Duration::from_secs(2);
+        // The slow endpoint comes first, so it is polled first
+        let fetcher = fetcher(vec![Server::new(slow, EndpointOptions::default()),
+                                   Server::new(fast, EndpointOptions::default())], 4, request_timeout);
+        let mut polls = HashMap::new();
+        let mut last_polled = HashMap::new();
+        let started = Instant::now();
+        get_servers(&fetcher, &targets(&fetcher).await, &mut polls, &mut last_polled, Duration::from_secs(1));
+
+        loop {
+            let servers = fetcher.servers.lock().await;
+            if servers[1].resources.hostname == "fast" {
+                assert!(started.elapsed() < request_timeout, "the fast server took {:?}", started.elapsed());
+                assert!(servers[1].health.last_error.is_none());
+                assert!(servers[0].resources.hostname.is_empty());
+                assert!(servers[0].health.last_error.is_none(), "the slow server's poll was already over");
+                break;
+            }
+            drop(servers);
+            assert!(started.elapsed() < request_timeout, "the fast server wasn't updated before the slow one timed out");
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        // A poll still running isn't started again, and the slow one times out on its own
+        let (slow_id, fast_id) = {
+            let servers = fetcher.servers.lock().await;
+            (servers[0].id, servers[1].id)
+        };
+        let (slow_polled, fast_polled) = (last_polled[&slow_id], last_polled[&fast_id]);
+        sleep(Duration::from_millis(10)).await;
+        get_servers(&fetcher, &targets(&fetcher).await, &mut polls, &mut last_polled, Duration::ZERO);
+        assert_eq!(last_polled[&slow_id], slow_polled, "the running slow poll was started again");
+        assert!(last_polled[&fast_id] > fast_polled, "the finished fast poll wasn't started again");
+        polls.remove(&slow_id).unwrap().await.unwrap();
+        assert!(started.elapsed() >= request_timeout);
+        let servers = fetcher.servers.lock().await;
+        assert_eq!(servers[0].health.last_error, Some(FetchError::Timeout));
+        assert!(servers[0].resources.hostname.is_empty());
+    }
+
+    #[tokio::test]
+    async fn polls_are_limited_to_the_concurrency() {
+        let in_flight = Arc::new(InFlight::default());
+        let mut servers = vec![];
+        for _ in 0..6 {
+            let endpoint = mock_agent("agent", "resources", Duration::from_millis(200), in_flight.clone()).await;
+            servers.push(Server::new(endpoint, EndpointOptions::default()));
+        }
+        let fetcher = fetcher(servers, 2, Duration::from_secs(5));
+        let mut polls = HashMap::new();
+        let mut last_polled = HashMap::new();
+        get_servers(&fetcher, &targets(&fetcher).await, &mut polls, &mut last_polled, Duration::from_secs(60));
+        assert_eq!(polls.len(), 6, "every endpoint gets a task, the permits hold them back");
+
+        for (_, poll) in polls.drain() {
+            poll.await.unwrap();
+        }
+        assert_eq!(in_flight.most.load(Ordering::SeqCst), 2);
+        assert!(fetcher.servers.lock().await.iter().all(|server| server.resources.hostname == "agent"));
+    }
+}
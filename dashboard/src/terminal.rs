@@ -3,7 +3,7 @@ use std::{
     time::Duration,
 };
 
-use std::sync::{Arc};
+use std::sync::{Arc, RwLock};
 use std::time::Instant;
 
 use anyhow::{Context, Result};
@@ -12,10 +12,12 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use crossterm::event::KeyEventKind;
+use crossterm::event::{KeyEventKind, KeyModifiers};
 use ratatui::{prelude::*};
 use tokio::sync::Mutex;
-use crate::app::App;
+use crate::app::{App, Appearance};
+use crate::endpoint_file::EndpointFile;
+use schema::ProcessSort;
 use crate::server::Server;
 use crate::ui;
 
@@ -50,14 +52,16 @@ pub fn initialize_panic_handler() {
 
 /// Runs the TUI loop. We setup the terminal environment, draw the application and react to user input
 /// and updates the data to be drawn on each tick. Once loop is exited we restore the terminal
-pub async fn run(servers: Arc<Mutex<Vec<Server>>>, tick_rate: u64, update_interval: u64) -> Result<()> {
+pub async fn run(servers: Arc<Mutex<Vec<Server>>>, tick_rate: u64, update_interval: u64,
+                 process_sort: Arc<RwLock<ProcessSort>>, appearance: Appearance,
+                 endpoint_file: Option<EndpointFile>, autosave: bool) -> Result<()> {
     initialize_panic_handler();
     let mut terminal = setup_terminal()?;
     let tick = Duration::from_millis(tick_rate);
     
-    let mut app = App::new(String::from("Dashboard"), tick_rate, update_interval);
+    let mut app = App::new(String::from("Dashboard"), tick_rate, update_interval, process_sort, appearance);
+    app.endpoint_file = endpoint_file;
     let mut last_tick = Instant::now();
-    let mut new_endpoint: Option<Server> = None;
 
     loop {
         terminal.draw(|f| ui::draw(f, &mut app))?;
@@ -69,13 +73,11 @@ pub async fn run(servers: Arc<Mutex<Vec<Server>>>, tick_rate: u64, update_interv
         if event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
                 if key.kind == KeyEventKind::Press {
+                    app.status_message = None;
                     //Read keyboard input from here
                     if app.show_endpoint_popup{
                         match key.code {
-                            KeyCode::Enter => {
-                                new_endpoint = Some(app.endpoint_input.add_endpoint());
-                                app.show_endpoint_popup = false;
-                            },
+                            KeyCode::Enter => app.on_enter(),
                             KeyCode::Char(to_insert) => {
                                 app.endpoint_input.enter_char(to_insert);
                             }
@@ -94,13 +96,19 @@ pub async fn run(servers: Arc<Mutex<Vec<Server>>>, tick_rate: u64, update_interv
                             _ => {}
                         }
                     }
+                    else if app.confirm_removal {
+                        app.on_confirm_removal(key.code == KeyCode::Char('y'));
+                    }
                     else{
                         match key.code {
+                            KeyCode::Up if key.modifiers.contains(KeyModifiers::SHIFT) => app.move_selected_server(true),
+                            KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) => app.move_selected_server(false),
                             KeyCode::Char(c) => app.on_key(c),
                             KeyCode::Left => app.on_left(),
                             KeyCode::Right => app.on_right(),
                             KeyCode::Up => app.on_up(),
                             KeyCode::Down => app.on_down(),
+                            KeyCode::Tab => app.on_tab(),
                             _ => {}
                         }
                     }
@@ -109,8 +117,11 @@ pub async fn run(servers: Arc<Mutex<Vec<Server>>>, tick_rate: u64, update_interv
         }
         // On each tick we update the data to be drawn in the next iteration
         if last_tick.elapsed() >= tick {
-            if let Some(server) = new_endpoint.take() {
-                servers.lock().await.push(server);
+            if !app.server_edits.is_empty() {
+                let mut servers = servers.lock().await;
+                for edit in app.server_edits.drain(..) {
+                    edit.apply(&mut servers);
+                }
             }
             app.on_tick(servers.lock().await.to_vec());
             last_tick = Instant::now();
@@ -121,6 +132,11 @@ pub async fn run(servers: Arc<Mutex<Vec<Server>>>, tick_rate: u64, update_interv
     }
     //Restore when we're done
     restore_terminal(&mut terminal)?;
+    if autosave {
+        if let Err(err) = app.save_endpoints() {
+            eprintln!("Failed to save the servers: {}", err);
+        }
+    }
     Ok(())
 }
 
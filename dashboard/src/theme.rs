@@ -0,0 +1,82 @@
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// The colour scheme of the dashboard, chosen in the config file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// For terminals with a dark background
+    #[default]
+    Dark,
+    /// For terminals with a light background, where yellow and light colours are hard to read
+    Light,
+}
+
+/// The colours the UI is drawn with
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    /// Gauges and temperatures well below their limits
+    pub ok: Color,
+    pub warning: Color,
+    pub critical: Color,
+    /// The selected tab, server and the process table header
+    pub highlight: Color,
+    pub tabs: Color,
+    pub cpu: Color,
+    pub memory: Color,
+    /// The parts of the memory breakdown
+    pub buffers: Color,
+    pub cached: Color,
+    pub shared: Color,
+    pub dirty: Color,
+    pub received: Color,
+    pub transmitted: Color,
+    /// Disk reads and writes
+    pub read: Color,
+    pub written: Color,
+    /// Servers that aren't online and other secondary text
+    pub muted: Color,
+}
+
+impl Theme {
+    pub fn palette(self) -> Palette {
+        match self {
+            Theme::Dark => Palette {
+                ok: Color::Green,
+                warning: Color::LightYellow,
+                critical: Color::Red,
+                highlight: Color::Yellow,
+                tabs: Color::Green,
+                cpu: Color::Green,
+                memory: Color::Blue,
+                buffers: Color::LightBlue,
+                cached: Color::Cyan,
+                shared: Color::LightMagenta,
+                dirty: Color::LightRed,
+                received: Color::Magenta,
+                transmitted: Color::Yellow,
+                read: Color::Cyan,
+                written: Color::LightRed,
+                muted: Color::DarkGray,
+            },
+            Theme::Light => Palette {
+                ok: Color::Green,
+                warning: Color::Rgb(190, 100, 0),
+                critical: Color::Red,
+                highlight: Color::Blue,
+                tabs: Color::Black,
+                cpu: Color::Green,
+                memory: Color::Blue,
+                buffers: Color::Rgb(70, 110, 200),
+                cached: Color::Rgb(0, 130, 130),
+                shared: Color::Magenta,
+                dirty: Color::Red,
+                received: Color::Magenta,
+                transmitted: Color::Rgb(190, 100, 0),
+                read: Color::Rgb(0, 130, 130),
+                written: Color::Red,
+                muted: Color::Gray,
+            },
+        }
+    }
+}
@@ -1,12 +1,15 @@
 use std::ops::Index;
+use std::time::Duration;
 use ratatui::Frame;
 use ratatui::layout::Direction::{Horizontal};
 use ratatui::prelude::*;
-use ratatui::style::Color::{Magenta, Yellow};
 use ratatui::widgets::*;
 use ratatui::widgets::block::{Position, Title};
-use crate::app::App;
-use crate::util::{bytes_to_gb, bytes_to_gib, centered_rect, format_kilobytes, format_seconds, kbs_to_mbps, log_scale, used_as_percentage, used_percentage};
+use crate::app::{next_process_sort, App, DetailView, RamSample};
+use crate::health::ConnectionStatus;
+use crate::server::Server;
+use crate::theme::Palette;
+use crate::util::{centered_rect, format_seconds, kbs_to_mbps, log_scale, used_as_percentage, used_percentage};
 
 pub fn draw(f: &mut Frame, app: &mut App){
     let chunks = Layout::default()
@@ -36,25 +39,29 @@ fn draw_tabs(f: &mut Frame, app: &mut App, area: Rect){
         .map(| t| {
             text::Line::from(Span::styled(
                 t,
-                Style::default().fg(Color::Green)))
+                Style::default().fg(app.palette.tabs)))
         })
         .collect();
 
+    let mut block = Block::default().borders(Borders::ALL)
+        .title(app.title.clone());
+    if let Some(message) = &app.status_message {
+        block = block.title(Title::from(message.as_str()).alignment(Alignment::Right));
+    }
     let tabs = Tabs::new(titles)
-        .block(Block::default().borders(Borders::ALL)
-            .title(app.title.clone()))
-        .highlight_style(Style::default().fg(Yellow))
+        .block(block)
+        .highlight_style(Style::default().fg(app.palette.highlight))
         .select(app.tabs.index);
     f.render_widget(tabs, area);
 }
 
-fn draw_gauge(f: &mut Frame, percentage: u16, title: &str, area: Rect) {
+fn draw_gauge(f: &mut Frame, palette: &Palette, percentage: u16, title: &str, area: Rect) {
     let gauge_style = if percentage > 90 {
-        Style::default().fg(Color::Red)
+        Style::default().fg(palette.critical)
     } else if percentage > 80 {
-        Style::default().fg(Color::LightYellow)
+        Style::default().fg(palette.warning)
     } else {
-        Style::default().fg(Color::Green)
+        Style::default().fg(palette.ok)
     };
 
     let gauge = Gauge::default()
@@ -66,25 +73,23 @@ fn draw_gauge(f: &mut Frame, percentage: u16, title: &str, area: Rect) {
 
 fn draw_server(f: &mut Frame, app: &mut App, index: usize, area: Rect) {
     let server = app.servers.get(index).unwrap();
-    let block = Block::default().borders(Borders::ALL).title(server.hostname.clone());
-    f.render_widget(block, area);
 
-    let gauge_constraints = vec![Constraint::Percentage(20); 5];
+    let gauge_constraints = vec![Constraint::Ratio(1, 6); 6];
     let gauge_chunks = Layout::default()
         .direction(Horizontal)
         .constraints(gauge_constraints)
         .margin(2)
         .split(area);
 
-    let uptime = Paragraph::new(format_seconds(server.uptime))
+    let uptime = Paragraph::new(format_seconds(server.resources.uptime))
         .block(Block::default()
             .borders(Borders::ALL)
             .title("Uptime")
             .padding(Padding::new(0, 0, 1, 0 )))
         .alignment(Alignment::Center);
 
-    if let Some(received_data) = app.received_chart_data.get(&(index)) {
-        if let Some(transmitted_data) = app.transmitted_chart_data.get(&(index)) {
+    if let Some(received_data) = app.received_chart_data.get(&server.id) {
+        if let Some(transmitted_data) = app.transmitted_chart_data.get(&server.id) {
             let rx = received_data.last().unwrap();
             let tx = transmitted_data.last().unwrap();
             let network = Paragraph::new(format!("{:.1} Mbps | {:.1} Mbps", kbs_to_mbps(*rx as u64), kbs_to_mbps(*tx as u64)))
@@ -93,18 +98,105 @@ fn draw_server(f: &mut Frame, app: &mut App, index: usize, area: Rect) {
                     .title("Network I/O")
                     .padding(Padding::new(0, 0, 1, 0 )))
                 .alignment(Alignment::Center);
-            f.render_widget(network, gauge_chunks[4]);
+            f.render_widget(network, gauge_chunks[5]);
         }
     }
 
     f.render_widget(uptime, gauge_chunks[0]);
 
-    draw_gauge(f, server.cpu_usage as u16, "CPU Usage", gauge_chunks[1]);
-    draw_gauge(f, used_as_percentage(server.used_memory as f64, server.total_memory as f64) as u16,
-               "Memory Usage", gauge_chunks[2]);
-    draw_gauge(f, used_percentage(server.available_space, server.total_space) as u16,
-               "Disk Usage", gauge_chunks[3]);
+    let cpu_title = if server.resources.cpu_limited() { "CPU Usage of Limit" } else { "CPU Usage" };
+    draw_gauge(f, &app.palette, server.resources.cpu_usage as u16, cpu_title, gauge_chunks[1]);
+    let memory_title = if server.resources.memory_limited() { "Memory Usage of Limit" } else { "Memory Usage" };
+    draw_gauge(f, &app.palette, used_as_percentage(server.resources.used_memory as f64, server.resources.total_memory as f64) as u16,
+               memory_title, gauge_chunks[2]);
+    // Hosts without swap show an empty gauge rather than a division by zero
+    let swap_percentage = if server.resources.total_swap > 0 {
+        used_as_percentage(server.resources.used_swap as f64, server.resources.total_swap as f64)
+    } else {
+        0.0
+    };
+    draw_gauge(f, &app.palette, swap_percentage as u16, "Swap Usage", gauge_chunks[3]);
+    draw_gauge(f, &app.palette, used_percentage(server.resources.available_space, server.resources.total_space) as u16,
+               "Disk Usage", gauge_chunks[4]);
+
+    let selected = index == app.scroll.selected as usize;
+    draw_server_block(f, &app.palette, server, Duration::from_millis(app.update_interval), selected, area);
+}
+
+/// Draws the border of a server with its name, tags and connection status. Drawn last, since a server
+/// we haven't heard from in a while has everything in its area greyed out first.
+/// A server with a poll interval of its own is expected to answer that often instead
+fn draw_server_block(f: &mut Frame, palette: &Palette, server: &Server, update_interval: Duration, selected: bool, area: Rect) {
+    let status = server.health.status(server.options.poll_interval.unwrap_or(update_interval));
+    if status != ConnectionStatus::Online {
+        f.buffer_mut().set_style(area, Style::default().fg(palette.muted));
+    }
+    let border_style = if selected {
+        Style::default().fg(palette.highlight)
+    } else {
+        Style::default()
+    };
+    let mut title = vec![Span::raw(server.name().to_string())];
+    if !server.options.tags.is_empty() {
+        title.push(Span::styled(format!(" [{}]", server.options.tags.join(", ")), Style::default().fg(palette.muted)));
+    }
+    if server.resources.from_newer_agent() {
+        title.push(Span::styled(format!(" newer agent, schema v{} ", server.resources.schema_version),
+                                Style::default().fg(Color::Black).bg(palette.warning)));
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(border_style)
+        .title(Line::from(title))
+        .title(Title::from(health_badge(palette, server, status)).alignment(Alignment::Right));
+    f.render_widget(block, area);
+}
+
+/// The latency of a server that is online, otherwise its status and why
+fn health_badge(palette: &Palette, server: &Server, status: ConnectionStatus) -> Span<'static> {
+    let health = &server.health;
+    match status {
+        ConnectionStatus::Online => {
+            let latency = health.latency.map(|latency| latency.as_millis()).unwrap_or(0);
+            Span::styled(format!(" {} ms ", latency), Style::default().fg(palette.ok))
+        }
+        ConnectionStatus::Connecting => {
+            Span::styled(format!(" {} ", status), Style::default().fg(Color::Black).bg(Color::Gray))
+        }
+        ConnectionStatus::Stale => {
+            let last_seen = health.last_success.map(|last_success| last_success.elapsed().as_secs()).unwrap_or(0);
+            Span::styled(format!(" {} · last seen {}s ago ", status, last_seen),
+                         Style::default().fg(Color::Black).bg(palette.warning))
+        }
+        ConnectionStatus::Offline => {
+            let reason = health.last_error.as_ref().map(|err| err.to_string()).unwrap_or_default();
+            Span::styled(format!(" {} · {} ", status, reason), Style::default().fg(Color::White).bg(palette.critical))
+        }
+    }
+}
+
+/// The last error of a server, with how long it has been failing, for the bottom of the detailed view
+fn health_details(palette: &Palette, server: &Server) -> Option<Line<'static>> {
+    let health = &server.health;
+    let error = health.last_error.as_ref()?;
+    if health.consecutive_failures == 0 {
+        return Some(Line::styled(format!(" Recovered from: {} ", error), Style::default().fg(palette.muted)));
+    }
+    let last_seen = match health.last_success {
+        Some(last_success) => format!("last seen {} ago", format_elapsed(last_success.elapsed())),
+        None => String::from("never answered"),
+    };
+    Some(Line::styled(format!(" Last error: {} · {} failures in a row · {} ", error, health.consecutive_failures, last_seen),
+                      Style::default().fg(palette.critical)))
+}
 
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format_seconds(seconds)
+    }
 }
 
 fn draw_server_overview(f: &mut Frame, app: &mut App, area: Rect) {
@@ -114,7 +206,8 @@ fn draw_server_overview(f: &mut Frame, app: &mut App, area: Rect) {
         let position = app.scroll.scroll_pos;
         let terminal_height = f.size().height;
         let view_length_fraction = 0.12;  // Adjust this to find a suitable size
-        let view_length = (terminal_height as f64 * view_length_fraction) as u16;
+        let view_length = ((terminal_height as f64 * view_length_fraction) as u16).max(1);
+        app.scroll.view_length = view_length;
         let end_index = position + view_length.min(no_of_servers - position);
         let subarea_height = area.height / view_length;
         app.scroll.vertical_scroll_state = app.scroll.vertical_scroll_state.content_length(app.scroll.scroll_content_length as usize);
@@ -145,96 +238,145 @@ fn draw_server_overview(f: &mut Frame, app: &mut App, area: Rect) {
 
 fn draw_detailed_view(f: &mut Frame, app: &mut App, area: Rect) {
     let current_index = app.tabs.index - 1; // The overview is always first index
-    let block = Block::default().borders(Borders::ALL).title(app.servers.index(current_index).hostname.clone());
-    f.render_widget(block, area);
 
-    let chunk_height = area.height / 3;
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(1)
-        .constraints([Constraint::Length(chunk_height); 3].as_ref())
-        .split(area);
+    if app.detail_view == DetailView::Processes {
+        draw_process_table(f, app, area.inner(&Margin { vertical: 1, horizontal: 1 }));
+    } else if app.detail_view == DetailView::Cgroups {
+        draw_cgroup_table(f, app, area.inner(&Margin { vertical: 1, horizontal: 1 }));
+    } else {
+        let chunk_height = area.height / 3;
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints([Constraint::Length(chunk_height); 3].as_ref())
+            .split(area);
+
+        draw_cpu_row(f, app, chunks[0]);
+        draw_memory_row(f, app, chunks[1]);
+        draw_info_network_row(f, app, chunks[2]);
+    }
 
-    draw_cpu_row(f, app, chunks[0]);
-    draw_memory_row(f, app, chunks[1]);
-    draw_info_network_row(f, app, chunks[2]);
+    let server = app.servers.index(current_index);
+    draw_server_block(f, &app.palette, server, Duration::from_millis(app.update_interval), false, area);
+    if let Some(details) = health_details(&app.palette, server) {
+        let details = Block::default()
+            .title(Title::from(details).position(Position::Bottom).alignment(Alignment::Left));
+        f.render_widget(details, area.inner(&Margin { vertical: 0, horizontal: 1 }));
+    }
 }
 
 fn draw_cpu_row(f: &mut Frame, app: &mut App, area: Rect) {
     let chunks = Layout::default()
         .direction(Horizontal)
-        .constraints([Constraint::Percentage(85), Constraint::Percentage(15)].as_ref())
+        .constraints([Constraint::Percentage(62), Constraint::Percentage(23), Constraint::Percentage(15)].as_ref())
         .margin(0)
         .split(area);
 
     draw_cpu_chart(f, app, chunks[0]);
-    draw_cpu_table(f, app, chunks[1]);
+    draw_sensor_table(f, app, chunks[1]);
+    draw_cpu_table(f, app, chunks[2]);
 }
 
 fn draw_memory_row(f: &mut Frame, app: &mut App, area: Rect) {
     let chunks = Layout::default()
         .direction(Horizontal)
-        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .constraints([Constraint::Percentage(35), Constraint::Percentage(25), Constraint::Percentage(40)].as_ref())
         .margin(0)
         .split(area);
 
     draw_ram_chart(f, app, chunks[0]);
-    draw_disk_table(f, app, chunks[1]);
+    draw_disk_io_chart(f, app, chunks[1]);
+    draw_disk_table(f, app, chunks[2]);
 }
 
 fn draw_info_network_row(f: &mut Frame, app: &mut App, area: Rect){
     let chunks = Layout::default()
         .direction(Horizontal)
-        .constraints([Constraint::Percentage(80), Constraint::Percentage(20)].as_ref())
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(20), Constraint::Percentage(20)].as_ref())
         .margin(0)
         .split(area);
 
     let sub_chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-        .split(chunks[1]);
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(chunks[2]);
 
     draw_network_chart(f, app, chunks[0]);
+    draw_pressure_table(f, app, chunks[1]);
     draw_network_info_list(f, app, sub_chunks[0]);
     draw_info_list(f, app, sub_chunks[1]);
 }
 
 fn draw_ram_chart(f: &mut Frame, app: &mut App, area: Rect) {
     let current_tab_index = app.tabs.index;
-    if let Some(ram_data) = app.ram_chart_data.get(&(current_tab_index - 1)) {
-        let used = bytes_to_gib(app.servers.get(current_tab_index - 1).unwrap().used_memory);
-        let total = bytes_to_gib(app.servers.get(current_tab_index - 1).unwrap().total_memory);
-        let percentage = used_as_percentage(used, total);
-
-        let data: Vec<(f64, f64)> = ram_data
-            .iter()
-            .enumerate()
-            .map(|(i, &val)| (i as f64, val as f64))
-            .collect();
-
-        let dataset = vec![
-            Dataset::default()
-                .marker(Marker::Braille)
-                .graph_type(GraphType::Line)
-                .style(Style::default().fg(Color::Blue))
-                .data(&data)];
+    let server = &app.servers[current_tab_index - 1];
+    if let Some(ram_data) = app.ram_chart_data.get(&server.id) {
+        let used = server.resources.used_memory;
+        let total = server.resources.total_memory;
+        let percentage = used_as_percentage(used as f64, total as f64);
+        let memory_limit = server.resources.limits.as_ref()
+            .and_then(|limits| limits.memory)
+            .map(|memory| app.units.format_bytes(memory));
+
+        // Buffers and cache are stacked on top of what processes use, shared and dirty memory are part of the cache
+        let points = |part: fn(&RamSample) -> f64| -> Vec<(f64, f64)> {
+            ram_data.iter().enumerate().map(|(i, sample)| (i as f64, part(sample))).collect()
+        };
+        let used_data = points(|sample| sample.used);
+        let buffers_data = points(|sample| sample.used + sample.buffers);
+        let cached_data = points(|sample| sample.used + sample.buffers + sample.cached);
+        let shared_data = points(|sample| sample.shared);
+        let dirty_data = points(|sample| sample.dirty);
+
+        let line = |name: &'static str, color: Color, data| Dataset::default()
+            .name(name)
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(color))
+            .data(data);
+        // Agents that don't break the memory down only have the used line, which needs no legend
+        let dataset = match server.resources.memory {
+            Some(_) if !server.resources.memory_limited() => vec![
+                line("Dirty", app.palette.dirty, &dirty_data),
+                line("Shared", app.palette.shared, &shared_data),
+                line("Cached", app.palette.cached, &cached_data),
+                line("Buffers", app.palette.buffers, &buffers_data),
+                line("Used", app.palette.memory, &used_data),
+            ],
+            _ => vec![line("", app.palette.memory, &used_data)],
+        };
+
+        let swap = if server.resources.total_swap == 0 {
+            String::from("No swap")
+        } else {
+            let (swapped_in, swapped_out) = app.swap_rates.get(&server.id).copied().unwrap_or_default();
+            format!("Swap {}/{} in {}/s out {}/s", app.units.format_bytes(server.resources.used_swap),
+                    app.units.format_bytes(server.resources.total_swap),
+                    app.units.format_bytes(swapped_in as u64), app.units.format_bytes(swapped_out as u64))
+        };
 
         let chart = Chart::new(dataset)
             .block(Block::new()
                 .borders(Borders::ALL)
                 .title(
-                    Title::from("RAM")
+                    Title::from(limit_title("RAM", memory_limit, server.resources.memory_limited()))
                         .position(Position::Top)
                         .alignment(Alignment::Left),
                 )
                 .title(
-                    Title::from(format!("{:.1}%\t {:.1}GiB/{:.1}GiB", percentage, used, total))
+                    Title::from(format!("{:.1}%\t {}/{}", percentage, app.units.format_bytes(used), app.units.format_bytes(total)))
                         .position(Position::Top)
                         .alignment(Alignment::Right),
+                )
+                .title(
+                    Title::from(swap)
+                        .position(Position::Bottom)
+                        .alignment(Alignment::Right),
                 ))
+            .hidden_legend_constraints((Constraint::Ratio(1, 3), Constraint::Ratio(3, 4)))
             .x_axis(Axis::default()
                 .bounds([0.0, ram_data.len() as f64 - 1.0])
-                .labels(["60s", "0s"].iter().cloned().map(Span::from).collect()))
+                .labels(chart_window_labels(app)))
             .y_axis(Axis::default()
                 .bounds([0.0, 100.0])
                 .labels(["0%", "100%"].iter().cloned().map(Span::from).collect()));
@@ -243,13 +385,25 @@ fn draw_ram_chart(f: &mut Frame, app: &mut App, area: Rect) {
     }
 }
 
+/// Names the limit of the agent's cgroup in a chart title, and whether the usage is measured against it
+fn limit_title(name: &str, limit: Option<String>, relative: bool) -> String {
+    match limit {
+        Some(limit) if relative => format!("{} of the {} limit", name, limit),
+        Some(limit) => format!("{} (limit {})", name, limit),
+        None => name.to_string(),
+    }
+}
+
 fn draw_cpu_chart(f: &mut Frame, app: &mut App, area: Rect){
     let current_server_index = app.tabs.index - 1;
-    if let Some(cpu_data) = app.cpu_chart_data.get(&(current_server_index)) {
-        let one = app.servers.get(current_server_index).unwrap().load_avg_one;
-        let five = app.servers.get(current_server_index).unwrap().load_avg_five;
-        let fifteen = app.servers.get(current_server_index).unwrap().load_avg_fifteen;
-        let usage = app.servers.get(current_server_index).unwrap().cpu_usage;
+    if let Some(cpu_data) = app.cpu_chart_data.get(&app.servers[current_server_index].id) {
+        let one = app.servers.get(current_server_index).unwrap().resources.load_avg_one;
+        let five = app.servers.get(current_server_index).unwrap().resources.load_avg_five;
+        let fifteen = app.servers.get(current_server_index).unwrap().resources.load_avg_fifteen;
+        let usage = app.servers.get(current_server_index).unwrap().resources.cpu_usage;
+        let resources = &app.servers.get(current_server_index).unwrap().resources;
+        let cpu_limit = resources.limits.as_ref().and_then(|limits| limits.cpus).map(|cpus| format!("{:.2} CPU", cpus));
+        let title = limit_title("CPU", cpu_limit, resources.cpu_limited());
 
         let data: Vec<(f64, f64)> = cpu_data
             .iter()
@@ -260,12 +414,12 @@ fn draw_cpu_chart(f: &mut Frame, app: &mut App, area: Rect){
         let dataset = vec![Dataset::default()
             .marker(Marker::Braille)
             .graph_type(GraphType::Line)
-            .style(Style::default().fg(Color::Green))
+            .style(Style::default().fg(app.palette.cpu))
             .data(&data)];
 
         let chart = Chart::new(dataset)
             .block(Block::default()
-                .title(Title::from("CPU")
+                .title(Title::from(title)
                             .position(Position::Top)
                             .alignment(Alignment::Left))
                 .title(Title::from(format!("{:.2} {:.2} {:.2}", one, five, fifteen))
@@ -278,7 +432,7 @@ fn draw_cpu_chart(f: &mut Frame, app: &mut App, area: Rect){
                 .borders(Borders::ALL))
             .x_axis(Axis::default()
                 .bounds([0.0, (data.len() - 1) as f64])
-                .labels(["60s", "0s"].iter().cloned().map(Span::from).collect()))
+                .labels(chart_window_labels(app)))
             .y_axis(Axis::default()
                 .bounds([0.0, 100.0])
                 .labels(["0%", "100%"].iter().cloned().map(Span::from).collect()));
@@ -288,9 +442,14 @@ fn draw_cpu_chart(f: &mut Frame, app: &mut App, area: Rect){
 }
 
 
+/// The x axis labels of a chart, from the start of the chart window to now
+fn chart_window_labels(app: &App) -> Vec<Span<'static>> {
+    vec![Span::from(format!("{}s", app.chart_window.as_secs())), Span::from("0s")]
+}
+
 fn draw_cpu_table(f: &mut Frame, app: &mut App, area: Rect){
     let server_index = app.tabs.index - 1;
-    let load_per_cores = &app.servers.get(server_index).unwrap().cpu_load_per_core;
+    let load_per_cores = &app.servers.get(server_index).unwrap().resources.cpu_load_per_core;
 
     let mut rows: Vec<Row> = vec![];
     let header_row = Row::new(vec!["CPU", "Use"])
@@ -317,49 +476,310 @@ fn draw_cpu_table(f: &mut Frame, app: &mut App, area: Rect){
     f.render_stateful_widget(table, area, &mut app.cpu_table.state);
 }
 
+/// Colours a temperature by how close it is to the critical threshold of the sensor
+fn temperature_style(palette: &Palette, temperature: f32, critical: Option<f32>) -> Style {
+    match critical {
+        Some(critical) if critical > 0.0 => {
+            let ratio = temperature / critical;
+            if ratio >= 0.9 {
+                Style::default().fg(palette.critical)
+            } else if ratio >= 0.75 {
+                Style::default().fg(palette.warning)
+            } else {
+                Style::default().fg(palette.ok)
+            }
+        }
+        _ => Style::default(),
+    }
+}
+
+/// Colours the share of the time tasks were stalled, a few percent is normal on a busy host
+fn pressure_style(palette: &Palette, percent: f32) -> Style {
+    if percent >= 40.0 {
+        Style::default().fg(palette.critical)
+    } else if percent >= 10.0 {
+        Style::default().fg(palette.warning)
+    } else {
+        Style::default().fg(palette.ok)
+    }
+}
+
+/// The Pressure Stall Information of the server, how much of the time tasks waited for each resource
+fn draw_pressure_table(f: &mut Frame, app: &App, area: Rect) {
+    let pressure = &app.servers[app.tabs.index - 1].resources.pressure;
+    let header_row = Row::new(vec!["Stalled", "10s", "60s", "300s"])
+        .style(Style::default())
+        .height(1);
+
+    let rows: Vec<Row> = match pressure {
+        // The CPU has no full line worth showing, it's always zero for the host as a whole
+        Some(pressure) => [("CPU some", Some(&pressure.cpu.some)),
+                           ("Memory some", Some(&pressure.memory.some)),
+                           ("Memory full", pressure.memory.full.as_ref()),
+                           ("I/O some", Some(&pressure.io.some)),
+                           ("I/O full", pressure.io.full.as_ref())]
+            .into_iter()
+            .filter_map(|(label, stall)| {
+                let stall = stall?;
+                let cell = |percent: f32| Cell::from(format!("{:.1}%", percent)).style(pressure_style(&app.palette, percent));
+                Some(Row::new(vec![Cell::from(label), cell(stall.avg10), cell(stall.avg60), cell(stall.avg300)]))
+            })
+            .collect(),
+        None => vec![],
+    };
+
+    let title = if pressure.is_some() { "Pressure" } else { "No pressure information" };
+    let table = Table::new(rows)
+        .header(header_row)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(title))
+        .widths(&[
+            Constraint::Percentage(37),
+            Constraint::Percentage(21),
+            Constraint::Percentage(21),
+            Constraint::Percentage(21)
+        ]);
+    f.render_widget(table, area);
+}
+
+fn draw_sensor_table(f: &mut Frame, app: &mut App, area: Rect){
+    let server_index = app.tabs.index - 1;
+    let sensors = &app.servers.get(server_index).unwrap().resources.sensors;
+    let header_row = Row::new(vec!["Sensor", "Temp", "Max", "Crit"])
+        .style(Style::default())
+        .height(1);
+
+    let format_temperature = |temperature: Option<f32>| {
+        temperature.map(|temperature| format!("{:.0}°C", temperature)).unwrap_or_else(|| String::from("-"))
+    };
+    let rows: Vec<Row> = sensors.iter()
+        .map(|sensor| {
+            Row::new(vec![
+                sensor.label.clone(),
+                format_temperature(Some(sensor.temperature)),
+                format_temperature(sensor.max),
+                format_temperature(sensor.critical),
+            ]).style(temperature_style(&app.palette, sensor.temperature, sensor.critical))
+        })
+        .collect();
+
+    let title = if sensors.is_empty() { "No sensors" } else { "Sensors" };
+    let table = Table::new(rows)
+        .header(header_row)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(title))
+        .widths(&[
+            Constraint::Percentage(43),
+            Constraint::Percentage(19),
+            Constraint::Percentage(19),
+            Constraint::Percentage(19)
+        ]);
+    f.render_widget(table, area);
+}
+
+/// Read and write rates of the selected block device, or of all of them
+fn draw_disk_io_chart(f: &mut Frame, app: &App, area: Rect) {
+    let server = &app.servers[app.tabs.index - 1];
+    let (Some(read_data), Some(written_data)) = (app.disk_read_chart_data.get(&server.id),
+                                                 app.disk_written_chart_data.get(&server.id)) else {
+        return;
+    };
+    let max = read_data.iter().chain(written_data).copied().fold(1024.0, f64::max);
+    let to_points = |data: &[f64]| -> Vec<(f64, f64)> {
+        data.iter().enumerate().map(|(i, &rate)| (i as f64, rate)).collect()
+    };
+    let (read, written) = (to_points(read_data), to_points(written_data));
+    let datasets = vec![
+        Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(app.palette.read))
+            .data(&read),
+        Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(app.palette.written))
+            .data(&written),
+    ];
+
+    let rate = |data: &[f64]| format!("{}/s", app.units.format_bytes(data.last().copied().unwrap_or(0.0) as u64));
+    let rates = Line::from(vec![
+        Span::styled(format!("R {}", rate(read_data)), Style::default().fg(app.palette.read)),
+        Span::raw(" "),
+        Span::styled(format!("W {}", rate(written_data)), Style::default().fg(app.palette.written)),
+    ]);
+    let chart = Chart::new(datasets)
+        .block(Block::default()
+            .title(Title::from(format!("Disk I/O: {}", app.disk_filter.title()))
+                .position(Position::Top)
+                .alignment(Alignment::Left))
+            .title(Title::from(rates)
+                .position(Position::Top)
+                .alignment(Alignment::Right))
+            .borders(Borders::ALL))
+        .x_axis(Axis::default()
+            .bounds([0.0, (read_data.len().max(2) - 1) as f64])
+            .labels(chart_window_labels(app)))
+        .y_axis(Axis::default()
+            .bounds([0.0, max])
+            .labels(vec![Span::from("0 B/s"), Span::from(format!("{}/s", app.units.format_bytes(max as u64)))]));
+
+    f.render_widget(chart, area);
+}
+
 fn draw_disk_table(f: &mut Frame, app: &mut App, area: Rect){
     let server_index = app.tabs.index - 1;
-    let header_row = Row::new(vec!["Disk", "Used", "Free", "Total"])
+    let header_row = Row::new(vec!["Mount", "Type", "Used", "Free", "Total", "Inodes"])
         .style(Style::default())
         .bottom_margin(1)
         .height(1);
-    let mut rows: Vec<Row> = vec![];
 
-    let disk_names = app.servers.get(server_index).unwrap().disk_names.clone();
-    let disk_available = &app.servers.get(server_index).unwrap().disk_available;
-    let disk_total = &app.servers.get(server_index).unwrap().disk_total;
-
-    for (i, disk) in disk_names.iter().enumerate() {
-        let disk_used = disk_total[i] - disk_available[i];
-        let mut disk_row = vec![];
-        disk_row.push(disk.to_string());
-        disk_row.push(format!("{:.1}GB", bytes_to_gb(disk_used)));
-        disk_row.push(format!("{:.1}GB", bytes_to_gb(disk_available[i])));
-        disk_row.push(format!("{:.1}GB", bytes_to_gb(disk_total[i])));
-        rows.push(Row::new(disk_row));
-    }
+    let resources = &app.servers.get(server_index).unwrap().resources;
+    // Older agents don't send the mount points, filesystem types or inodes, the name stands in for the mount point
+    let mut disks: Vec<(f64, Row)> = resources.disk_names.iter().enumerate().map(|(i, name)| {
+        let total = resources.disk_total[i];
+        let available = resources.disk_available[i];
+        let used_percent = if total > 0 { used_percentage(available, total) } else { 0.0 };
+        let mut mount_point = resources.disk_mount_points.get(i).unwrap_or(name).clone();
+        if resources.disk_removable.get(i) == Some(&true) {
+            mount_point.push_str(" ⏏");
+        }
+        let inodes = match (resources.disk_inodes_total.get(i), resources.disk_inodes_used.get(i)) {
+            (Some(&total), Some(&used)) if total > 0 => format!("{:.0}%", used as f64 / total as f64 * 100.0),
+            _ => "-".to_string(),
+        };
+        (used_percent, Row::new(vec![
+            mount_point,
+            resources.disk_file_systems.get(i).cloned().unwrap_or_else(|| "-".to_string()),
+            format!("{:.0}%", used_percent),
+            app.units.format_bytes(available),
+            app.units.format_bytes(total),
+            inodes,
+        ]))
+    }).collect();
+    // The fullest disks first
+    disks.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let rows: Vec<Row> = disks.into_iter().map(|(_, row)| row).collect();
 
     let table = Table::new(rows)
         .header(header_row)
         .block(Block::default()
             .borders(Borders::ALL))
         .widths(&[
-            Constraint::Percentage(40),
-            Constraint::Percentage(20),
-            Constraint::Percentage(20),
-            Constraint::Percentage(20)
+            Constraint::Percentage(32),
+            Constraint::Percentage(13),
+            Constraint::Percentage(11),
+            Constraint::Percentage(15),
+            Constraint::Percentage(15),
+            Constraint::Percentage(14)
         ]);
 
     f.render_widget(table, area);
 }
 
+fn draw_process_table(f: &mut Frame, app: &mut App, area: Rect){
+    let sort = app.process_sort();
+    let header_row = Row::new(vec!["PID", "User", "CPU", "Memory", "Virtual", "Status", "Name", "Command"])
+        .style(Style::default().fg(app.palette.highlight))
+        .height(1);
+
+    let units = app.units;
+    let rows: Vec<Row> = app.current_processes()
+        .iter()
+        .map(|process| {
+            Row::new(vec![
+                process.pid.to_string(),
+                process.user.clone(),
+                format!("{:.1}%", process.cpu_usage),
+                units.format_bytes(process.memory),
+                units.format_bytes(process.virtual_memory),
+                process.status.clone(),
+                process.name.clone(),
+                process.command.clone(),
+            ])
+        })
+        .collect();
+    app.process_table.table.size = rows.len();
+
+    let table = Table::new(rows)
+        .header(header_row)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(Title::from("Processes")
+                .position(Position::Top)
+                .alignment(Alignment::Left))
+            .title(Title::from(format!("Sorted by {}", sort))
+                .position(Position::Top)
+                .alignment(Alignment::Right)))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .widths(&[
+            Constraint::Length(8),
+            Constraint::Length(10),
+            Constraint::Length(7),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(20),
+            Constraint::Percentage(100),
+        ]);
+    f.render_stateful_widget(table, area, &mut app.process_table.table.state);
+}
+
+fn draw_cgroup_table(f: &mut Frame, app: &mut App, area: Rect){
+    let header_row = Row::new(vec!["CPU", "Memory", "Limit", "Read", "Written", "PIDs", "Cgroup"])
+        .style(Style::default().fg(app.palette.highlight))
+        .height(1);
+
+    let units = app.units;
+    let cgroups = app.current_cgroups();
+    let rows: Vec<Row> = cgroups.iter()
+        .map(|cgroup| {
+            Row::new(vec![
+                format!("{:.1}%", cgroup.cpu_usage),
+                units.format_bytes(cgroup.memory_current),
+                cgroup.memory_max.map(|max| units.format_bytes(max)).unwrap_or_else(|| String::from("-")),
+                units.format_bytes(cgroup.io_read_bytes),
+                units.format_bytes(cgroup.io_written_bytes),
+                cgroup.pids.to_string(),
+                cgroup.path.clone(),
+            ])
+        })
+        .collect();
+    let title = if rows.is_empty() { "No cgroups" } else { "Cgroups" };
+    app.cgroup_table.size = rows.len();
+
+    let table = Table::new(rows)
+        .header(header_row)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(Title::from(title)
+                .position(Position::Top)
+                .alignment(Alignment::Left))
+            .title(Title::from(format!("Sorted by {}", app.cgroup_sort))
+                .position(Position::Top)
+                .alignment(Alignment::Right)))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .widths(&[
+            Constraint::Length(8),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(7),
+            Constraint::Percentage(100),
+        ]);
+    f.render_stateful_widget(table, area, &mut app.cgroup_table.state);
+}
+
 fn draw_info_list(f: &mut Frame, app: &mut App, area: Rect){
     let server_index = app.tabs.index - 1;
     let mut items: Vec<ListItem> = vec![];
-    items.push(ListItem::new(format!("OS: {}", app.servers.get(server_index).unwrap().os_version)));
-    items.push(ListItem::new(format!("Kernel: {}", app.servers.get(server_index).unwrap().kernel_version)));
-    items.push(ListItem::new(format!("Hostname: {}", app.servers.get(server_index).unwrap().hostname)));
-    items.push(ListItem::new(format!("Uptime: {}", format_seconds(app.servers.get(server_index).unwrap().uptime))));
+    items.push(ListItem::new(format!("OS: {}", app.servers.get(server_index).unwrap().resources.os_version)));
+    items.push(ListItem::new(format!("Kernel: {}", app.servers.get(server_index).unwrap().resources.kernel_version)));
+    items.push(ListItem::new(format!("Hostname: {}", app.servers.get(server_index).unwrap().resources.hostname)));
+    items.push(ListItem::new(format!("Uptime: {}", format_seconds(app.servers.get(server_index).unwrap().resources.uptime))));
 
     let list = List::new(items)
         .block(Block::default()
@@ -370,20 +790,22 @@ fn draw_info_list(f: &mut Frame, app: &mut App, area: Rect){
 
 fn draw_network_chart(f: &mut Frame, app: &App, area: Rect) {
     let current_server_index = app.tabs.index - 1;
-    if let Some(received_data) = app.received_chart_data.get(&(current_server_index)) {
-        if let Some(transmitted_data) = app.transmitted_chart_data.get(&(current_server_index)){
+    let server_id = app.servers[current_server_index].id;
+    if let Some(received_data) = app.received_chart_data.get(&server_id) {
+        if let Some(transmitted_data) = app.transmitted_chart_data.get(&server_id){
+            let totals = app.network_filter.combined(app.servers.get(current_server_index).unwrap());
             let greeting = Paragraph::new(format!("RX: {} KB/S   TX: {} KB/S RX TOTAL: {} TX TOTAL: {}",
                                                   received_data.last().unwrap(),
                                                   transmitted_data.last().unwrap(),
-                                                  app.servers.get(current_server_index).unwrap().bytes_received,
-                                                  app.servers.get(current_server_index).unwrap().bytes_transmitted));
+                                                  totals.received,
+                                                  totals.transmitted));
             f.render_widget(greeting, area);
             let mut max_rx = *received_data.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap_or(&0.0);
             if max_rx < 100.0 { max_rx = 100.0;}
             let scaled_rx: Vec<f64> = received_data.iter().map(|&val| log_scale(val, max_rx)).collect();
             let scaled_tx: Vec<f64> = transmitted_data.iter().map(|&val| log_scale(val, max_rx)).collect();
 
-            let max_string= format!("{}/s", format_kilobytes(max_rx as u64));
+            let max_string= format!("{}/s", app.units.format_bytes(max_rx as u64 * 1024));
             let my_str_ref: &str = max_string.as_str();
 
             let rx : Vec<(f64, f64)> = scaled_rx
@@ -401,12 +823,12 @@ fn draw_network_chart(f: &mut Frame, app: &App, area: Rect) {
                 Dataset::default()
                     .marker(Marker::Braille)
                     .graph_type(GraphType::Line)
-                    .style(Style::default().fg(Magenta))
+                    .style(Style::default().fg(app.palette.received))
                     .data(&rx),
                 Dataset::default()
                     .marker(Marker::Braille)
                     .graph_type(GraphType::Line)
-                    .style(Style::default().fg(Yellow))
+                    .style(Style::default().fg(app.palette.transmitted))
                     .data(&tx),
             ];
 
@@ -415,14 +837,17 @@ fn draw_network_chart(f: &mut Frame, app: &App, area: Rect) {
                     .title(Title::from("Network")
                         .position(Position::Top)
                         .alignment(Alignment::Left))
+                    .title(Title::from(totals.name)
+                        .position(Position::Top)
+                        .alignment(Alignment::Right))
                     .borders(Borders::ALL))
 
                 .x_axis(Axis::default()
                     .bounds([0.0, (received_data.len() - 1) as f64])
-                    .labels(["60s", "0s"].iter().cloned().map(Span::from).collect()))
+                    .labels(chart_window_labels(app)))
                 .y_axis(Axis::default()
                     .bounds([0.0, 100.0])
-                    .labels(["0 KB/s", my_str_ref].iter().cloned().map(Span::from).collect()));
+                    .labels(["0 B/s", my_str_ref].iter().cloned().map(Span::from).collect()));
 
             f.render_widget(chart, area);
         }
@@ -431,17 +856,23 @@ fn draw_network_chart(f: &mut Frame, app: &App, area: Rect) {
 
 fn draw_network_info_list(f: &mut Frame, app: &mut App, area: Rect){
     let current_server_index = app.tabs.index - 1;
-    if let Some(received_data) = app.received_chart_data.get(&(current_server_index)) {
-        if let Some(transmitted_data) = app.transmitted_chart_data.get(&(current_server_index)) {
+    let server_id = app.servers[current_server_index].id;
+    if let Some(received_data) = app.received_chart_data.get(&server_id) {
+        if let Some(transmitted_data) = app.transmitted_chart_data.get(&server_id) {
+            let totals = app.network_filter.combined(app.servers.get(current_server_index).unwrap());
             let mut items: Vec<ListItem> = vec![];
-            items.push(ListItem::new(format!("RX: {}/s", format_kilobytes(*received_data.last().unwrap() as u64)))
-                .style(Style::default().fg(Magenta)));
-            items.push(ListItem::new(format!("TX: {}/s", format_kilobytes(*transmitted_data.last().unwrap() as u64)))
-                .style(Style::default().fg(Yellow)));
-            items.push(ListItem::new(format!("Total: {}", format_kilobytes(app.servers.get(current_server_index).unwrap().bytes_received / 1024)))
-                .style(Style::default().fg(Magenta)));
-            items.push(ListItem::new(format!("Total: {}", format_kilobytes(app.servers.get(current_server_index).unwrap().bytes_transmitted / 1024)))
-                .style(Style::default().fg(Yellow)));
+            let (units, palette) = (app.units, app.palette);
+            items.push(ListItem::new(format!("RX: {}/s", units.format_bytes((*received_data.last().unwrap() * 1024.0) as u64)))
+                .style(Style::default().fg(palette.received)));
+            items.push(ListItem::new(format!("TX: {}/s", units.format_bytes((*transmitted_data.last().unwrap() * 1024.0) as u64)))
+                .style(Style::default().fg(palette.transmitted)));
+            items.push(ListItem::new(format!("Total: {}", units.format_bytes(totals.received)))
+                .style(Style::default().fg(palette.received)));
+            items.push(ListItem::new(format!("Total: {}", units.format_bytes(totals.transmitted)))
+                .style(Style::default().fg(palette.transmitted)));
+            items.push(ListItem::new(format!("Packets: {} / {}", totals.packets_received, totals.packets_transmitted)));
+            items.push(ListItem::new(format!("Errors: {} / {}", totals.errors_received, totals.errors_transmitted)));
+            items.push(ListItem::new(format!("Drops: {} / {}", totals.drops_received, totals.drops_transmitted)));
 
             let list = List::new(items)
                 .block(Block::default()
@@ -457,11 +888,16 @@ fn draw_endpoint_popup(f: &mut Frame, app: &mut App){
     let area = centered_rect(60, 20, size);
     f.render_widget(Clear, area); //this clears out the background
 
+    let title = match &app.endpoint_input.error {
+        Some(err) => Span::styled(err.as_str(), Style::default().fg(app.palette.critical)),
+        None if app.endpoint_input.editing.is_some() => Span::raw("Edit the server endpoint, the token is kept unless token=<token> is given"),
+        None => Span::raw("Enter a server endpoint, optionally followed by token=<token>"),
+    };
     let input = Paragraph::new(app.endpoint_input.input.as_str())
         .block(Block::default()
             .borders(Borders::ALL)
             .title_alignment(Alignment::Center)
-            .title("Enter a server endpoint"));
+            .title(title));
     f.render_widget(input, area);
 
     //Set cursor
@@ -471,10 +907,23 @@ fn draw_endpoint_popup(f: &mut Frame, app: &mut App){
 }
 
 fn draw_key_legend(f: &mut Frame, app: &mut App, area: Rect){
-    let title = if app.show_endpoint_popup{
-       "Esc: Cancel \t Enter: Add"
+    let title = if app.show_endpoint_popup && app.endpoint_input.editing.is_some(){
+       String::from("Esc: Cancel \t Enter: Save")
+    }else if app.show_endpoint_popup{
+       String::from("Esc: Cancel \t Enter: Add")
+    }else if app.confirm_removal{
+       let name = app.servers.get(app.scroll.selected as usize).map(|server| server.name()).unwrap_or_default();
+       format!("Remove {}? 'y': Yes \t Any other key: No", name)
+    }else if app.showing_processes(){
+       format!("Navigate: ◄ ▲ ▼ ► \t Tab: Cgroups \t 's': Sort by {} \t 'q': Exit the application",
+               next_process_sort(app.process_sort()))
+    }else if app.showing_cgroups(){
+       format!("Navigate: ◄ ▲ ▼ ► \t Tab: Resources \t 's': Sort by {} \t 'q': Exit the application",
+               app.cgroup_sort.next())
+    }else if app.tabs.index != 0{
+       String::from("Navigate: ◄ ▲ ▼ ► \t Tab: Processes \t 'i': Network interface \t 'o': Disk \t 'p': Add a server \t 'q': Exit the application")
     }else{
-       "Navigate: ◄ ▲ ▼ ► \t 'p': Add a server \t 'q': Exit the application"
+       String::from("Navigate: ◄ ▲ ▼ ► \t 'p': Add a server \t 'e': Edit \t 'd': Delete \t Shift+▲ ▼: Move \t 'w': Save \t 'q': Exit the application")
     };
     let title = Block::default()
         .title(title)
@@ -1,7 +1,7 @@
-use std::fs::File;
-use std::io;
-use std::io::BufRead;
+use std::time::Duration;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use serde::Deserialize;
+use crate::server::EndpointOptions;
 
 /// Shows the total usage as percentage
 pub fn used_as_percentage(used: f64, total: f64) -> f64 {
@@ -14,15 +14,6 @@ pub fn used_percentage(available: u64, total: u64) -> f64 {
     (used as f64 / total as f64) * 100.0
 }
 
-pub fn bytes_to_gib(bytes: u64) -> f64 {
-    const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
-    bytes as f64 / GIB
-}
-
-pub fn bytes_to_gb(bytes: u64) -> f64 {
-    bytes as f64 / 1_000_000_000.0
-}
-
 /// Formats seconds to into a dd/hh/mm/ss String
 pub fn format_seconds(seconds: u64) -> String {
     let days = seconds / (24 * 3600);
@@ -50,42 +41,107 @@ pub fn log_scale(value: f64, max_value: f64) -> f64 {
     }
 }
 
-pub fn format_kilobytes(kilobytes: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * KB;
-    const GB: u64 = KB * MB;
+/// Whether sizes are shown in powers of 1024 (KiB, MiB, GiB) or 1000 (kB, MB, GB)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Binary,
+    Decimal,
+}
 
-    if kilobytes < KB {
-        format!("{} KB", kilobytes)
-    } else if kilobytes < MB {
-        format!("{:.2} MB", kilobytes as f64 / KB as f64)
-    } else if kilobytes < GB {
-        format!("{:.2} GB", kilobytes as f64 / MB as f64)
-    } else {
-        format!("{:.2} TB", kilobytes as f64 / GB as f64)
+impl Units {
+    /// Formats a size in the largest unit it reaches, e.g. 1.5 GiB
+    pub fn format_bytes(self, bytes: u64) -> String {
+        let (base, names) = match self {
+            Units::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB"]),
+            Units::Decimal => (1000.0, ["B", "kB", "MB", "GB", "TB"]),
+        };
+        let mut value = bytes as f64;
+        let mut unit = 0;
+        while value >= base && unit < names.len() - 1 {
+            value /= base;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} B", bytes)
+        } else {
+            format!("{:.1} {}", value, names[unit])
+        }
     }
 }
 
-/// Takes file path(s) as parameter, iterates over each file and return a vector of endpoints.
-/// Each endpoint should be separated by newline
-pub fn extract_endpoints_from_files(file_paths: Vec<String>) -> Vec<String> {
-    let mut endpoints: Vec<String> = Vec::new();
-    for file_path in file_paths {
-        match File::open(&file_path) {
-            Err(_) => {
-                eprintln!("Failed to open file: {}", file_path);
-                continue;
+/// Parses an endpoint line: the URL followed by whitespace separated key=value options,
+/// e.g. `https://10.0.0.2:3000/resources token=secret insecure=true name=db tags=prod,eu timeout=500`.
+/// Blank lines and comments starting with # give None
+pub fn parse_endpoint(line: &str) -> Result<Option<(String, EndpointOptions)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let mut words = line.split_whitespace();
+    let endpoint = words.next().unwrap_or_default().to_string();
+    let mut options = EndpointOptions::default();
+    for option in words {
+        match option.split_once('=') {
+            Some(("token", token)) if !token.is_empty() => options.token = Some(token.to_string()),
+            Some(("token", _)) => return Err("the token can't be empty".to_string()),
+            Some(("insecure", value)) => {
+                options.insecure = value.parse()
+                    .map_err(|_| format!("insecure must be true or false, got '{}'", value))?;
             }
-            Ok(file) => {
-                let reader = io::BufReader::new(file);
-                for line in reader.lines().flatten() {
-                    endpoints.push(line);
-                }
+            Some(("name", name)) if !name.is_empty() => options.name = Some(name.to_string()),
+            Some(("name", _)) => return Err("the name can't be empty".to_string()),
+            Some(("tags", tags)) => {
+                options.tags = tags.split(',').filter(|tag| !tag.is_empty()).map(String::from).collect();
             }
-        };
+            Some(("timeout", value)) => options.timeout = Some(parse_milliseconds("timeout", value)?),
+            Some(("poll_interval", value)) => options.poll_interval = Some(parse_milliseconds("poll_interval", value)?),
+            Some((key, _)) => return Err(format!("unknown option '{}'", key)),
+            None => return Err(format!("expected key=value, got '{}'", option)),
+        }
     }
+    Ok(Some((endpoint, options)))
+}
+
+/// Formats an endpoint and its options as a line of an endpoint file, the inverse of parse_endpoint
+pub fn format_endpoint(endpoint: &str, options: &EndpointOptions) -> String {
+    let mut line = endpoint.to_string();
+    if let Some(token) = &options.token {
+        line.push_str(&format!(" token={}", token));
+    }
+    if options.insecure {
+        line.push_str(" insecure=true");
+    }
+    if let Some(name) = &options.name {
+        line.push_str(&format!(" name={}", name));
+    }
+    if !options.tags.is_empty() {
+        line.push_str(&format!(" tags={}", options.tags.join(",")));
+    }
+    if let Some(timeout) = options.timeout {
+        line.push_str(&format!(" timeout={}", timeout.as_millis()));
+    }
+    if let Some(poll_interval) = options.poll_interval {
+        line.push_str(&format!(" poll_interval={}", poll_interval.as_millis()));
+    }
+    line
+}
+
+/// A duration given in milliseconds, which has to be more than zero
+fn parse_milliseconds(key: &str, value: &str) -> Result<Duration, String> {
+    match value.parse() {
+        Ok(milliseconds) if milliseconds > 0 => Ok(Duration::from_millis(milliseconds)),
+        _ => Err(format!("{} must be a number of milliseconds above 0, got '{}'", key, value)),
+    }
+}
 
-    endpoints
+/// Builds the URL for another route on the same agent. Endpoints are normally given as
+/// the URL to the /resources route, e.g. http://localhost:3000/resources
+pub fn endpoint_route(endpoint: &str, route: &str) -> String {
+    let base = endpoint.trim_end_matches('/');
+    let base = base.strip_suffix("/resources").unwrap_or(base);
+    format!("{}/{}", base, route)
 }
 
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
@@ -0,0 +1,95 @@
+//! Measures the CPU time the agent spends refreshing the system information. The schedule of
+//! Resources is driven the way the agent's refresh loop drives it, and compared with refreshing
+//! everything on every update as the agent did before the collectors had intervals of their own.
+//! Every run takes the same time and is measured by the CPU time of the whole process, so nothing
+//! is extrapolated from timing single refreshes.
+//! Run with `cargo bench -p resource-monitor`, `BENCH_SECONDS` sets how long each run takes
+use std::mem::MaybeUninit;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use resource_monitor::config::Config;
+use resource_monitor::resources::Resources;
+use sysinfo::{System, SystemExt};
+
+/// How long each run takes unless BENCH_SECONDS is set
+const DEFAULT_SECONDS: u64 = 30;
+
+fn main() {
+    let period = std::env::var("BENCH_SECONDS").ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .map_or(Duration::from_secs(DEFAULT_SECONDS), Duration::from_secs);
+    let config = Config::default();
+    let update_frequency = Duration::from_millis(config.update_frequency);
+    println!("{} s per run, updates every {} ms", period.as_secs(), config.update_frequency);
+
+    let before = refresh_all(update_frequency, period);
+    report("before, refresh_all every update", before, before, period);
+    report("after, default intervals", follow_schedule(&config, period), before, period);
+    let mut config = Config::default();
+    config.intervals.cpu = Some(500);
+    config.intervals.processes = Some(5000);
+    config.intervals.sensors = Some(5000);
+    report("after, cpu 500 ms, processes and sensors 5 s", follow_schedule(&config, period), before, period);
+}
+
+/// Refreshes everything on every update, the CPU time it took
+fn refresh_all(update_frequency: Duration, period: Duration) -> Duration {
+    let mut sys = System::new_all();
+    sys.refresh_all();
+    measure(period, |deadline| {
+        let mut next = Instant::now() + update_frequency;
+        while next <= deadline {
+            sleep_until(next);
+            sys.refresh_all();
+            next += update_frequency;
+        }
+    })
+}
+
+/// Refreshes the collectors as they are due, the way the agent's refresh loop does, the CPU time it took
+fn follow_schedule(config: &Config, period: Duration) -> Duration {
+    let mut resources = Resources::new(config);
+    measure(period, |deadline| {
+        loop {
+            let next = resources.next_refresh();
+            if next > deadline {
+                break;
+            }
+            sleep_until(next);
+            resources.refresh(Instant::now());
+        }
+    })
+}
+
+/// The CPU time the process spent from the start of the run until the end of the period
+fn measure(period: Duration, run: impl FnOnce(Instant)) -> Duration {
+    let started = cpu_time();
+    let deadline = Instant::now() + period;
+    run(deadline);
+    sleep_until(deadline);
+    cpu_time() - started
+}
+
+/// The user and system CPU time of the process so far
+fn cpu_time() -> Duration {
+    let mut usage = MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes to the struct it is given
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        panic!("getrusage failed");
+    }
+    // SAFETY: getrusage filled in the struct as it succeeded
+    let usage = unsafe { usage.assume_init() };
+    let time = |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    time(usage.ru_utime) + time(usage.ru_stime)
+}
+
+fn sleep_until(instant: Instant) {
+    sleep(instant.saturating_duration_since(Instant::now()));
+}
+
+fn report(name: &str, cpu: Duration, before: Duration, period: Duration) {
+    let per_minute = cpu.as_secs_f64() * 1000.0 * 60.0 / period.as_secs_f64();
+    println!("{:<46} {:>10.1} ms CPU per minute {:>6.2}% of a core {:>6.0}% of before",
+             name, per_minute, cpu.as_secs_f64() / period.as_secs_f64() * 100.0,
+             cpu.as_secs_f64() / before.as_secs_f64() * 100.0);
+}
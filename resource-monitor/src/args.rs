@@ -1,14 +1,59 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
 use clap:: {Parser};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// The frequency which the system metrics are updated. Given in milliseconds
-    #[arg(short('r'), long("update"), default_value = "1000")]
-    pub update_frequency: u64,
+    /// Read the settings from this TOML file. The flags below override it
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
 
-    /// The port to listen for incoming requests
-    #[arg(short, long("port"), default_value = "3000")]
-    pub port: u16,
+    /// Print the effective config, the file with the flags applied, and exit
+    #[arg(long)]
+    pub print_config: bool,
 
+    /// An address to listen on, IPv4 or IPv6. Can be given more than once [default: 0.0.0.0]
+    #[arg(short, long, value_name = "ADDRESS")]
+    pub bind: Vec<IpAddr>,
+
+    /// Also serve plain HTTP on this Unix domain socket. Without --bind the agent then only listens on the socket
+    #[arg(long, value_name = "PATH")]
+    pub unix_socket: Option<PathBuf>,
+
+    /// The permissions of the Unix socket in octal, e.g. 660. Without it the umask decides
+    #[arg(long, value_name = "MODE")]
+    pub unix_socket_mode: Option<String>,
+
+    /// The frequency which the system metrics are updated, unless a collector has an interval of its own in the config file. Given in milliseconds [default: 1000]
+    #[arg(short('r'), long("update"))]
+    pub update_frequency: Option<u64>,
+
+    /// The port to listen for incoming requests [default: 3000]
+    #[arg(short, long("port"))]
+    pub port: Option<u16>,
+
+    /// How many samples of metrics history to keep for the /history route [default: 300]
+    #[arg(short('s'), long("history"))]
+    pub history_length: Option<usize>,
+
+    /// Require this bearer token on every request
+    #[arg(long, env = "RESOURCE_MONITOR_TOKEN", hide_env_values = true, conflicts_with = "token_file")]
+    pub token: Option<String>,
+
+    /// Read the bearer token to require on every request from a file
+    #[arg(long, value_name = "PATH")]
+    pub token_file: Option<PathBuf>,
+
+    /// Serve HTTPS with this PEM certificate chain. Reloaded together with the key on SIGHUP
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// The PEM private key of the certificate
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Only accept clients presenting a certificate signed by one of the CAs in this PEM bundle
+    #[arg(long, value_name = "PATH")]
+    pub client_ca: Option<PathBuf>,
 }
\ No newline at end of file
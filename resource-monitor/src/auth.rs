@@ -0,0 +1,109 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{Request, StatusCode};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use crate::config::Auth;
+
+/// Reads the token given in the config, with --token, the RESOURCE_MONITOR_TOKEN environment variable
+/// or from a token file. No token means every request is let through
+pub fn load_token(auth: &Auth) -> Result<Option<String>, String> {
+    let token = match (&auth.token, &auth.token_file) {
+        (Some(token), _) => token.clone(),
+        (None, Some(path)) => fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read token file {}: {}", path.display(), err))?
+            .trim()
+            .to_string(),
+        (None, None) => return Ok(None),
+    };
+    if token.is_empty() {
+        return Err("The token can't be empty".to_string());
+    }
+    Ok(Some(token))
+}
+
+/// Middleware rejecting requests that don't carry the token in an `Authorization: Bearer` header
+pub async fn require_token<B>(State(token): State<Arc<String>>,
+                              remote: Option<ConnectInfo<SocketAddr>>,
+                              request: Request<B>, next: Next<B>) -> Response {
+    let given = request.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    let reason = match given {
+        Some(given) if constant_time_eq(given.trim().as_bytes(), token.as_bytes()) => {
+            return next.run(request).await;
+        }
+        Some(_) => "invalid token",
+        None => "missing token",
+    };
+    // Requests over the Unix socket have no remote address
+    let remote = match remote {
+        Some(ConnectInfo(remote)) => remote.to_string(),
+        None => "the Unix socket".to_string(),
+    };
+    eprintln!("Rejected request for {} from {}: {}", request.uri().path(), remote, reason);
+    (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")], "Unauthorized").into_response()
+}
+
+/// Compares without returning early, so the time taken doesn't reveal how much of the token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{middleware, Router};
+    use axum::body::Body;
+    use axum::routing::get;
+    use hyper::service::Service;
+    use super::*;
+
+    #[test]
+    fn equal_bytes_only() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn token_file_is_trimmed() {
+        let path = std::env::temp_dir().join(format!("resource-monitor-token-{}", std::process::id()));
+        fs::write(&path, "secret\n").unwrap();
+        let token = load_token(&Auth { token: None, token_file: Some(path.clone()) });
+        fs::write(&path, "\n").unwrap();
+        let empty = load_token(&Auth { token: None, token_file: Some(path.clone()) });
+        fs::remove_file(&path).unwrap();
+        assert_eq!(token, Ok(Some("secret".to_string())));
+        assert_eq!(empty, Err("The token can't be empty".to_string()));
+        assert_eq!(load_token(&Auth::default()), Ok(None));
+    }
+
+    async fn status(authorization: Option<&str>) -> StatusCode {
+        let mut app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(Arc::new("secret".to_string()), require_token));
+        let mut request = Request::builder().uri("/");
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        app.call(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn requests_need_the_token() {
+        assert_eq!(status(None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("Bearer wrong")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("secret")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("Bearer secret")).await, StatusCode::OK);
+    }
+}
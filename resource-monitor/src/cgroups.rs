@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use schema::Cgroup;
+use crate::config::Cgroups;
+
+/// Reads the cgroups of a cgroup v2 hierarchy. The CPU time of each cgroup is kept, so its usage
+/// can be worked out over the time between two reads
+#[derive(Debug)]
+pub struct CgroupReader {
+    root: PathBuf,
+    depth: usize,
+    previous_cpu_time: HashMap<String, u64>,
+    previous_read: Option<Instant>,
+}
+
+impl CgroupReader {
+    pub fn new(config: &Cgroups) -> Self {
+        CgroupReader {
+            root: config.root.clone(),
+            depth: config.depth,
+            previous_cpu_time: HashMap::new(),
+            previous_read: None,
+        }
+    }
+
+    /// The cgroups below the root, down to the configured depth. The root itself is left out as
+    /// it is the whole machine. A root that isn't a cgroup v2 hierarchy gives an empty list
+    pub fn read(&mut self) -> Vec<Cgroup> {
+        self.read_at(Instant::now())
+    }
+
+    fn read_at(&mut self, now: Instant) -> Vec<Cgroup> {
+        if !self.root.join("cgroup.controllers").is_file() {
+            return vec![];
+        }
+        let mut cgroups = vec![];
+        walk(&self.root, &self.root, self.depth, &mut cgroups);
+
+        // The usage is 0 on the first read, and for cgroups that weren't there on the previous one
+        if let Some(elapsed) = self.previous_read.map(|previous| now - previous).filter(|elapsed| !elapsed.is_zero()) {
+            for cgroup in cgroups.iter_mut() {
+                if let Some(previous) = self.previous_cpu_time.get(&cgroup.path) {
+                    let used = cgroup.cpu_time.saturating_sub(*previous) as f64;
+                    cgroup.cpu_usage = (used / elapsed.as_micros() as f64 * 100.0) as f32;
+                }
+            }
+        }
+        self.previous_cpu_time = cgroups.iter().map(|cgroup| (cgroup.path.clone(), cgroup.cpu_time)).collect();
+        self.previous_read = Some(now);
+        cgroups
+    }
+}
+
+/// Reads the cgroups in the directory and, while depth allows, the ones below them.
+/// Cgroups that are removed while they are read are skipped
+fn walk(root: &Path, dir: &Path, depth: usize, cgroups: &mut Vec<Cgroup>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut children: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false))
+        .map(|entry| entry.path())
+        .collect();
+    children.sort();
+    for child in children {
+        let Ok(relative) = child.strip_prefix(root) else {
+            continue;
+        };
+        cgroups.push(read_cgroup(&child, format!("/{}", relative.to_string_lossy())));
+        if depth > 1 {
+            walk(root, &child, depth - 1, cgroups);
+        }
+    }
+}
+
+/// Reads the interface files of a cgroup. Those of controllers that aren't enabled for it are
+/// missing, which leaves their fields at zero
+fn read_cgroup(dir: &Path, path: String) -> Cgroup {
+    let read = |file: &str| fs::read_to_string(dir.join(file)).unwrap_or_default();
+    let number = |file: &str| read(file).trim().parse().unwrap_or(0);
+    let (io_read_bytes, io_written_bytes) = parse_io_stat(&read("io.stat"));
+    Cgroup {
+        path,
+        cpu_usage: 0.0,
+        cpu_time: parse_keyed(&read("cpu.stat"), "usage_usec").unwrap_or(0),
+        memory_current: number("memory.current"),
+        // "max" when there is no limit
+        memory_max: read("memory.max").trim().parse().ok(),
+        io_read_bytes,
+        io_written_bytes,
+        pids: number("pids.current"),
+    }
+}
+
+/// Looks up the value of a line such as `usage_usec 1234`
+fn parse_keyed(contents: &str, key: &str) -> Option<u64> {
+    contents.lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(name, _)| *name == key)
+        .and_then(|(_, value)| value.trim().parse().ok())
+}
+
+/// Sums up the bytes read and written over the devices of io.stat, which has a line per device
+/// such as `8:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0`
+fn parse_io_stat(contents: &str) -> (u64, u64) {
+    contents.split_whitespace()
+        .filter_map(|field| field.split_once('='))
+        .fold((0, 0), |(read, written), (key, value)| match (key, value.parse::<u64>()) {
+            ("rbytes", Ok(bytes)) => (read + bytes, written),
+            ("wbytes", Ok(bytes)) => (read, written + bytes),
+            _ => (read, written),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::test_dir::TestDir;
+
+    /// A hierarchy three levels deep, with the controllers enabled for system.slice and nginx.service only
+    fn hierarchy(name: &str) -> TestDir {
+        let dir = TestDir::new(name);
+        dir.write("cgroup.controllers", "cpuset cpu io memory pids\n");
+        // The root's own files describe the whole machine and are never read
+        dir.write("cpu.stat", "usage_usec 999999999\n");
+        dir.write("system.slice/cpu.stat", "usage_usec 1000000\nuser_usec 600000\nsystem_usec 400000\n");
+        dir.write("system.slice/memory.current", "104857600\n");
+        dir.write("system.slice/memory.max", "max\n");
+        dir.write("system.slice/io.stat", "\
+8:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0
+259:0 rbytes=40960 wbytes=8192 rios=10 wios=2 dbytes=0 dios=0
+");
+        dir.write("system.slice/pids.current", "42\n");
+        dir.write("system.slice/nginx.service/cpu.stat", "usage_usec 400000\n");
+        dir.write("system.slice/nginx.service/memory.current", "52428800\n");
+        dir.write("system.slice/nginx.service/memory.max", "268435456\n");
+        dir.write("system.slice/nginx.service/pids.current", "5\n");
+        dir.write("system.slice/nginx.service/worker/cgroup.procs", "");
+        fs::create_dir_all(dir.path().join("user.slice")).unwrap();
+        dir
+    }
+
+    fn reader(dir: &TestDir, depth: usize) -> CgroupReader {
+        CgroupReader::new(&Cgroups { root: dir.path().to_path_buf(), depth })
+    }
+
+    fn paths(cgroups: &[Cgroup]) -> Vec<&str> {
+        cgroups.iter().map(|cgroup| cgroup.path.as_str()).collect()
+    }
+
+    #[test]
+    fn not_a_cgroup_v2_hierarchy() {
+        let dir = TestDir::new("cgroups-v1");
+        dir.write("cpu,cpuacct/cpuacct.usage", "1000\n");
+        assert!(reader(&dir, 2).read().is_empty());
+    }
+
+    #[test]
+    fn stops_at_the_depth() {
+        let dir = hierarchy("cgroups-depth");
+        assert_eq!(paths(&reader(&dir, 1).read()), ["/system.slice", "/user.slice"]);
+        assert_eq!(paths(&reader(&dir, 2).read()), ["/system.slice", "/system.slice/nginx.service", "/user.slice"]);
+        assert_eq!(paths(&reader(&dir, 3).read()),
+                   ["/system.slice", "/system.slice/nginx.service", "/system.slice/nginx.service/worker", "/user.slice"]);
+    }
+
+    #[test]
+    fn reads_the_interface_files() {
+        let dir = hierarchy("cgroups-files");
+        let cgroups = reader(&dir, 2).read();
+        assert_eq!(cgroups[0], Cgroup {
+            path: "/system.slice".to_string(),
+            cpu_usage: 0.0,
+            cpu_time: 1_000_000,
+            memory_current: 104_857_600,
+            memory_max: None,
+            io_read_bytes: 1_459_200 + 40_960,
+            io_written_bytes: 314_773_504 + 8_192,
+            pids: 42,
+        });
+        assert_eq!(cgroups[1].memory_max, Some(268_435_456));
+        assert_eq!(cgroups[1].io_read_bytes, 0);
+        // Without any controllers enabled everything is zero
+        assert_eq!(cgroups[2], Cgroup { path: "/user.slice".to_string(), ..Default::default() });
+    }
+
+    #[test]
+    fn cpu_usage_over_the_time_between_reads() {
+        let dir = hierarchy("cgroups-cpu");
+        let mut reader = reader(&dir, 2);
+        let first = Instant::now();
+        assert!(reader.read_at(first).iter().all(|cgroup| cgroup.cpu_usage == 0.0));
+
+        // Half a second of CPU time over two seconds
+        dir.write("system.slice/cpu.stat", "usage_usec 1500000\n");
+        dir.write("user.slice/cpu.stat", "usage_usec 300000\n");
+        let cgroups = reader.read_at(first + Duration::from_secs(2));
+        assert_eq!(cgroups[0].cpu_usage, 25.0);
+        assert_eq!(cgroups[1].cpu_usage, 0.0);
+        // Had no CPU time on the previous read, as the controller wasn't enabled
+        assert_eq!(cgroups[2].cpu_usage, 15.0);
+
+        // A cgroup that just appeared has no usage yet
+        dir.write("system.slice/cron.service/cpu.stat", "usage_usec 800000\n");
+        let cgroups = reader.read_at(first + Duration::from_secs(3));
+        assert_eq!(paths(&cgroups)[1], "/system.slice/cron.service");
+        assert_eq!(cgroups[1].cpu_usage, 0.0);
+        assert_eq!(cgroups[0].cpu_usage, 0.0);
+    }
+}
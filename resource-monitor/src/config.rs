@@ -0,0 +1,414 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Deserializer, Serialize};
+use schema::config::describe_error;
+use crate::args::Args;
+
+/// The effective settings of the agent: the config file, if one was given, with the command line
+/// flags applied on top. Every key of the file is optional and falls back to the default
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The addresses to listen on, a single address or a list of them
+    #[serde(deserialize_with = "one_or_many")]
+    pub bind: Vec<IpAddr>,
+    pub port: u16,
+    /// How often the system metrics are refreshed, in milliseconds, unless a collector has an interval of its own
+    pub update_frequency: u64,
+    /// How many samples of metrics history to keep for the /history route
+    pub history_length: usize,
+    pub collectors: Collectors,
+    pub intervals: Intervals,
+    /// Which disks are reported, matched against their name and mount point, or the name of the block device for their I/O
+    pub disks: Filter,
+    /// Which filesystem types the disks are reported for, e.g. `exclude = ["overlay", "squashfs"]`
+    pub file_systems: Filter,
+    /// Which network interfaces are reported, matched against their name
+    pub network: Filter,
+    pub cgroups: Cgroups,
+    pub container: Container,
+    pub auth: Auth,
+    pub tls: Tls,
+    pub unix_socket: UnixSocket,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            // Resolved in load, as the default depends on whether there is a Unix socket
+            bind: vec![],
+            port: 3000,
+            update_frequency: 1000,
+            history_length: 300,
+            collectors: Collectors::default(),
+            intervals: Intervals::default(),
+            disks: Filter::default(),
+            file_systems: Filter::default(),
+            network: Filter::default(),
+            cgroups: Cgroups::default(),
+            container: Container::default(),
+            auth: Auth::default(),
+            tls: Tls::default(),
+            unix_socket: UnixSocket::default(),
+        }
+    }
+}
+
+/// The optional parts of the resources, a disabled collector is never refreshed and reported empty
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Collectors {
+    /// The disk space and the I/O of the block devices
+    pub disks: bool,
+    pub network: bool,
+    /// Also serves the /processes route
+    pub processes: bool,
+    pub sensors: bool,
+    /// Pressure Stall Information, on Linux only
+    pub pressure: bool,
+    /// The cgroups of a cgroup v2 hierarchy, on Linux only
+    pub cgroups: bool,
+}
+
+impl Default for Collectors {
+    fn default() -> Self {
+        Collectors { disks: true, network: true, processes: true, sensors: true, pressure: true, cgroups: true }
+    }
+}
+
+/// How often each collector is refreshed, in milliseconds. Those without an interval follow
+/// update_frequency, except for the disks whose space rarely changes quickly
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Intervals {
+    /// The CPU usage and load averages
+    pub cpu: Option<u64>,
+    pub memory: Option<u64>,
+    /// The disk space, the disks are also rescanned so newly mounted ones show up
+    pub disks: Option<u64>,
+    /// The I/O counters of the block devices
+    pub disk_io: Option<u64>,
+    pub network: Option<u64>,
+    pub processes: Option<u64>,
+    pub sensors: Option<u64>,
+    pub pressure: Option<u64>,
+    pub cgroups: Option<u64>,
+}
+
+impl Default for Intervals {
+    fn default() -> Self {
+        Intervals {
+            cpu: None,
+            memory: None,
+            disks: Some(30_000),
+            disk_io: None,
+            network: None,
+            processes: None,
+            sensors: None,
+            pressure: None,
+            cgroups: None,
+        }
+    }
+}
+
+/// Glob patterns, where `*` matches any run of characters and `?` a single one.
+/// Everything is included when there are no include patterns, and exclude patterns win
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Filter {
+    /// Whether something known by any of the names passes the filter
+    pub fn allows(&self, names: &[&str]) -> bool {
+        let matches = |patterns: &[String]| {
+            patterns.iter().any(|pattern| names.iter().any(|name| glob_match(pattern, name)))
+        };
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+/// Where the cgroup v2 hierarchy is mounted and how many levels of it are reported
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cgroups {
+    /// Hosts with both cgroup versions usually have the v2 hierarchy at /sys/fs/cgroup/unified
+    pub root: PathBuf,
+    /// 1 only reports the cgroups directly below the root
+    pub depth: usize,
+}
+
+impl Default for Cgroups {
+    fn default() -> Self {
+        Cgroups { root: PathBuf::from("/sys/fs/cgroup"), depth: 2 }
+    }
+}
+
+/// How the limits of the cgroup the agent runs in, looked up below the cgroups root, are used
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Container {
+    /// Report the CPU and memory usage against the limits rather than the host, where there is a limit
+    pub relative_to_limits: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    /// Require this bearer token on every request
+    pub token: Option<String>,
+    /// Read the token from this file instead
+    pub token_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tls {
+    /// Serve HTTPS with this PEM certificate chain
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// Only accept clients presenting a certificate signed by one of these CAs
+    pub client_ca: Option<PathBuf>,
+}
+
+/// A Unix domain socket to serve plain HTTP on, for a reverse proxy on the same host
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnixSocket {
+    pub path: Option<PathBuf>,
+    /// The permissions of the socket in octal, e.g. "660". Without it the umask decides
+    pub mode: Option<String>,
+}
+
+impl UnixSocket {
+    /// The mode as permission bits, checked by Config::load
+    pub fn permissions(&self) -> Option<u32> {
+        self.mode.as_ref().and_then(|mode| parse_mode(mode))
+    }
+}
+
+impl Config {
+    /// Reads the file given with --config, applies the flags given on the command line and checks the result
+    pub fn load(args: &Args) -> Result<Config, String> {
+        let mut config = match &args.config {
+            Some(path) => read(path)?,
+            None => Config::default(),
+        };
+        config.apply(args);
+        // With only a Unix socket the agent isn't reachable over the network, unless addresses are given
+        if config.bind.is_empty() && config.unix_socket.path.is_none() {
+            config.bind = vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)];
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply(&mut self, args: &Args) {
+        if !args.bind.is_empty() {
+            self.bind = args.bind.clone();
+        }
+        if let Some(port) = args.port {
+            self.port = port;
+        }
+        if let Some(update_frequency) = args.update_frequency {
+            self.update_frequency = update_frequency;
+        }
+        if let Some(history_length) = args.history_length {
+            self.history_length = history_length;
+        }
+        // A token given on the command line replaces any token source from the file
+        if args.token.is_some() || args.token_file.is_some() {
+            self.auth = Auth { token: args.token.clone(), token_file: args.token_file.clone() };
+        }
+        if args.tls_cert.is_some() {
+            self.tls.cert = args.tls_cert.clone();
+            self.tls.key = args.tls_key.clone();
+        }
+        if args.client_ca.is_some() {
+            self.tls.client_ca = args.client_ca.clone();
+        }
+        if args.unix_socket.is_some() {
+            self.unix_socket.path = args.unix_socket.clone();
+        }
+        if args.unix_socket_mode.is_some() {
+            self.unix_socket.mode = args.unix_socket_mode.clone();
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.update_frequency == 0 {
+            return Err("update_frequency must be above 0".to_string());
+        }
+        let intervals = self.intervals;
+        for (collector, interval) in [("cpu", intervals.cpu), ("memory", intervals.memory), ("disks", intervals.disks),
+                                      ("disk_io", intervals.disk_io), ("network", intervals.network), ("processes", intervals.processes),
+                                      ("sensors", intervals.sensors), ("pressure", intervals.pressure),
+                                      ("cgroups", intervals.cgroups)] {
+            if interval == Some(0) {
+                return Err(format!("intervals: {} must be above 0", collector));
+            }
+        }
+        if self.history_length == 0 {
+            return Err("history_length must be above 0".to_string());
+        }
+        if self.cgroups.depth == 0 {
+            return Err("cgroups: depth must be above 0".to_string());
+        }
+        if self.auth.token.is_some() && self.auth.token_file.is_some() {
+            return Err("auth: token and token_file can't both be set".to_string());
+        }
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(_), None) => return Err("tls: cert is set without a key".to_string()),
+            (None, Some(_)) => return Err("tls: key is set without a cert".to_string()),
+            _ => {}
+        }
+        if self.tls.client_ca.is_some() && self.tls.cert.is_none() {
+            return Err("tls: client_ca requires a cert and key".to_string());
+        }
+        for (i, address) in self.bind.iter().enumerate() {
+            if self.bind[..i].contains(address) {
+                return Err(format!("bind: {} is given more than once", address));
+            }
+        }
+        if let Some(mode) = &self.unix_socket.mode {
+            if self.unix_socket.path.is_none() {
+                return Err("unix_socket: mode is set without a path".to_string());
+            }
+            if parse_mode(mode).is_none() {
+                return Err(format!("unix_socket: mode must be octal permissions such as 660, got '{}'", mode));
+            }
+        }
+        for (section, filter) in [("disks", &self.disks), ("file_systems", &self.file_systems), ("network", &self.network)] {
+            if filter.include.iter().chain(&filter.exclude).any(|pattern| pattern.is_empty()) {
+                return Err(format!("{}: patterns can't be empty", section));
+            }
+        }
+        Ok(())
+    }
+
+    /// The config as TOML, with the token left out so the output can be shared
+    pub fn to_toml(&self) -> String {
+        let mut printable = self.clone();
+        if printable.auth.token.is_some() {
+            printable.auth.token = Some("<redacted>".to_string());
+        }
+        toml::to_string(&printable).expect("the config is representable in TOML")
+    }
+}
+
+fn read(path: &Path) -> Result<Config, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read config file {}: {}", path.display(), err))?;
+    toml::from_str(&contents).map_err(|err| describe_error(path, &contents, err.message(), err.span()))
+}
+
+/// Accepts `bind = "::1"` as well as `bind = ["127.0.0.1", "::1"]`
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<IpAddr>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(IpAddr),
+        Many(Vec<IpAddr>),
+    }
+    match OneOrMany::deserialize(deserializer) {
+        Ok(OneOrMany::One(address)) => Ok(vec![address]),
+        Ok(OneOrMany::Many(addresses)) => Ok(addresses),
+        Err(_) => Err(serde::de::Error::custom("expected an IP address or a list of them")),
+    }
+}
+
+/// Permission bits given in octal, with or without a leading 0
+fn parse_mode(mode: &str) -> Option<u32> {
+    u32::from_str_radix(mode, 8).ok().filter(|bits| *bits <= 0o777)
+}
+
+/// Matches `*` against any run of characters and `?` against any single character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last * was and how much of the text it has taken so far, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use crate::test_dir::TestDir;
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*.service", "ssh.service"));
+        assert!(!glob_match("*.service", "ssh.socket"));
+        assert!(glob_match("sd*", "sda1"));
+        assert!(!glob_match("sd*", "nvme0n1"));
+        assert!(glob_match("*a*b*", "xaxxbx"));
+        assert!(glob_match("*a*b*", "ab"));
+        assert!(!glob_match("*a*b*", "ba"));
+        assert!(glob_match("veth????", "veth1a2b"));
+        assert!(!glob_match("veth????", "veth1a2"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "eth0"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn bind_takes_one_address_or_a_list() {
+        let config: Config = toml::from_str("bind = \"::1\"").unwrap();
+        assert_eq!(config.bind, vec!["::1".parse::<IpAddr>().unwrap()]);
+        let config: Config = toml::from_str("bind = [\"127.0.0.1\", \"::1\"]").unwrap();
+        assert_eq!(config.bind, vec!["127.0.0.1".parse::<IpAddr>().unwrap(), "::1".parse().unwrap()]);
+        let err = toml::from_str::<Config>("bind = \"localhost\"").unwrap_err();
+        assert!(err.message().contains("expected an IP address or a list of them"));
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let dir = TestDir::new("config-precedence");
+        dir.write("agent.toml", "port = 8000\nupdate_frequency = 500\nhistory_length = 60\n\n[auth]\ntoken_file = \"/etc/token\"\n");
+        let path = dir.path().join("agent.toml");
+        let args = Args::parse_from(["resource-monitor", "--config", path.to_str().unwrap(), "--port", "9000", "--token", "secret"]);
+        let config = Config::load(&args).unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.update_frequency, 500);
+        assert_eq!(config.history_length, 60);
+        // The token from the command line replaces the token file rather than conflicting with it
+        assert_eq!(config.auth.token.as_deref(), Some("secret"));
+        assert_eq!(config.auth.token_file, None);
+        assert_eq!(config.bind, vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)]);
+    }
+
+    #[test]
+    fn print_config_redacts_the_token() {
+        let config = Config { auth: Auth { token: Some("secret".to_string()), token_file: None }, ..Default::default() };
+        let printed = config.to_toml();
+        assert!(printed.contains("token = \"<redacted>\""));
+        assert!(!printed.contains("secret"));
+        assert_eq!(config.auth.token.as_deref(), Some("secret"));
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use schema::Limits;
+
+const PROC_SELF_CGROUP: &str = "/proc/self/cgroup";
+
+/// The CPU and memory limits of the cgroup the agent runs in, such as those of a container, and
+/// the cgroups they are set on so the usage can be read against them
+#[derive(Debug)]
+pub struct ContainerLimits {
+    pub limits: Limits,
+    cpu_cgroup: Option<PathBuf>,
+    memory_cgroup: Option<PathBuf>,
+    /// The CPU time of the cgroup with the CPU limit when it was last read, in microseconds
+    previous_cpu_time: Option<(u64, Instant)>,
+}
+
+impl ContainerLimits {
+    /// Looks up the cgroup v2 cgroup of the agent below the root. Gives None when it can't be
+    /// found, or neither it nor the cgroups above it are limited to less than the host has
+    pub fn detect(root: &Path, host_memory: u64, host_cpus: usize) -> Option<Self> {
+        let own_cgroups = fs::read_to_string(PROC_SELF_CGROUP).ok()?;
+        detect_in(root, &own_cgroups, host_memory, host_cpus)
+    }
+
+    /// The memory used by the cgroup with the memory limit
+    pub fn memory_used(&self) -> Option<u64> {
+        read_number(&self.memory_cgroup.as_ref()?.join("memory.current"))
+    }
+
+    /// The CPU usage of the cgroup with the CPU limit in percent of the limit, since the previous
+    /// call. The first call gives 0
+    pub fn cpu_usage(&mut self) -> Option<f32> {
+        self.cpu_usage_at(Instant::now())
+    }
+
+    fn cpu_usage_at(&mut self, now: Instant) -> Option<f32> {
+        let cpus = self.limits.cpus?;
+        let stat = fs::read_to_string(self.cpu_cgroup.as_ref()?.join("cpu.stat")).ok()?;
+        let cpu_time: u64 = stat.lines()
+            .find_map(|line| line.strip_prefix("usage_usec "))
+            .and_then(|value| value.trim().parse().ok())?;
+        let usage = match self.previous_cpu_time.replace((cpu_time, now)) {
+            Some((previous, read_at)) if now > read_at => {
+                let available = (now - read_at).as_micros() as f64 * cpus;
+                (cpu_time.saturating_sub(previous) as f64 / available * 100.0).min(100.0) as f32
+            }
+            _ => 0.0,
+        };
+        Some(usage)
+    }
+}
+
+/// Finds the cgroup v2 cgroup in the contents of /proc/self/cgroup, the line starting with `0::`,
+/// and takes the tightest limits set on it and the cgroups above it up to the root. Inside a
+/// container with a cgroup namespace the agent's own cgroup is the root
+fn detect_in(root: &Path, own_cgroups: &str, host_memory: u64, host_cpus: usize) -> Option<ContainerLimits> {
+    let own_path = own_cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let own_cgroup = root.join(own_path.trim().trim_start_matches('/'));
+
+    let (mut cpus, mut cpu_cgroup): (Option<f64>, Option<PathBuf>) = (None, None);
+    let (mut memory, mut memory_cgroup): (Option<u64>, Option<PathBuf>) = (None, None);
+    for cgroup in own_cgroup.ancestors().take_while(|cgroup| cgroup.starts_with(root)) {
+        if let Some(limit) = read_cpu_max(&cgroup.join("cpu.max")) {
+            if cpus.is_none_or(|cpus| limit < cpus) {
+                (cpus, cpu_cgroup) = (Some(limit), Some(cgroup.to_path_buf()));
+            }
+        }
+        if let Some(limit) = read_number(&cgroup.join("memory.max")) {
+            if memory.is_none_or(|memory| limit < memory) {
+                (memory, memory_cgroup) = (Some(limit), Some(cgroup.to_path_buf()));
+            }
+        }
+    }
+
+    // Limits the host doesn't reach anyway don't constrain anything
+    let cpus = cpus.filter(|cpus| *cpus < host_cpus as f64);
+    let memory = memory.filter(|memory| *memory < host_memory);
+    if cpus.is_none() && memory.is_none() {
+        return None;
+    }
+    Some(ContainerLimits {
+        limits: Limits { cpus, memory },
+        cpu_cgroup: cpus.and(cpu_cgroup),
+        memory_cgroup: memory.and(memory_cgroup),
+        previous_cpu_time: None,
+    })
+}
+
+/// cpu.max holds the quota and the period in microseconds, e.g. `150000 100000` for one and a half
+/// CPUs, or `max 100000` without a limit
+fn read_cpu_max(path: &Path) -> Option<f64> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut fields = contents.split_whitespace();
+    let quota: f64 = fields.next()?.parse().ok()?;
+    let period: f64 = fields.next()?.parse().ok()?;
+    Some(quota / period).filter(|_| period > 0.0)
+}
+
+/// A file holding a single number, "max" and missing files give None
+fn read_number(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    const GIB: u64 = 1 << 30;
+    /// A host with 8 CPUs and 16 GiB of memory
+    const HOST_CPUS: usize = 8;
+    const HOST_MEMORY: u64 = 16 * GIB;
+
+    fn detect(dir: &TestDir, own_cgroups: &str) -> Option<ContainerLimits> {
+        detect_in(dir.path(), own_cgroups, HOST_MEMORY, HOST_CPUS)
+    }
+
+    #[test]
+    fn cgroup_namespace_root() {
+        let dir = TestDir::new("container-namespace");
+        dir.write("cpu.max", "200000 100000\n");
+        dir.write("memory.max", "536870912\n");
+        dir.write("memory.current", "104857600\n");
+        let container = detect(&dir, "0::/\n").unwrap();
+        assert_eq!(container.limits, Limits { cpus: Some(2.0), memory: Some(512 << 20) });
+        assert_eq!(container.cpu_cgroup.as_deref(), Some(dir.path()));
+        assert_eq!(container.memory_used(), Some(100 << 20));
+    }
+
+    #[test]
+    fn tightest_limit_of_the_ancestors() {
+        let dir = TestDir::new("container-nested");
+        dir.write("kubepods/cpu.max", "max 100000\n");
+        dir.write("kubepods/pod1/cpu.max", "150000 100000\n");
+        dir.write("kubepods/pod1/memory.max", "max\n");
+        dir.write("kubepods/pod1/ctr/cpu.max", "400000 100000\n");
+        dir.write("kubepods/pod1/ctr/memory.max", "1073741824\n");
+        dir.write("kubepods/pod1/ctr/memory.current", "268435456\n");
+        let container = detect(&dir, "0::/kubepods/pod1/ctr\n").unwrap();
+        assert_eq!(container.limits, Limits { cpus: Some(1.5), memory: Some(GIB) });
+        assert_eq!(container.cpu_cgroup, Some(dir.path().join("kubepods/pod1")));
+        assert_eq!(container.memory_cgroup, Some(dir.path().join("kubepods/pod1/ctr")));
+        assert_eq!(container.memory_used(), Some(256 << 20));
+    }
+
+    #[test]
+    fn no_cpu_quota() {
+        let dir = TestDir::new("container-no-quota");
+        dir.write("docker/abc/cpu.max", "max 100000\n");
+        dir.write("docker/abc/memory.max", "2147483648\n");
+        let container = detect(&dir, "0::/docker/abc\n").unwrap();
+        assert_eq!(container.limits, Limits { cpus: None, memory: Some(2 * GIB) });
+        assert_eq!(container.cpu_cgroup, None);
+    }
+
+    #[test]
+    fn limits_the_host_does_not_reach() {
+        let dir = TestDir::new("container-above-host");
+        dir.write("docker/abc/cpu.max", "800000 100000\n");
+        dir.write("docker/abc/memory.max", &format!("{}\n", HOST_MEMORY));
+        assert!(detect(&dir, "0::/docker/abc\n").is_none());
+
+        dir.write("docker/abc/cpu.max", "1600000 100000\n");
+        dir.write("docker/abc/memory.max", &format!("{}\n", 2 * HOST_MEMORY));
+        assert!(detect(&dir, "0::/docker/abc\n").is_none());
+    }
+
+    #[test]
+    fn hybrid_hierarchy() {
+        let dir = TestDir::new("container-hybrid");
+        dir.write("docker/abc/cpu.max", "50000 100000\n");
+        let own_cgroups = "\
+12:cpu,cpuacct:/docker/abc
+11:memory:/docker/abc
+1:name=systemd:/docker/abc
+0::/docker/abc
+";
+        let container = detect(&dir, own_cgroups).unwrap();
+        assert_eq!(container.limits, Limits { cpus: Some(0.5), memory: None });
+
+        // Only cgroup v1, there is no cgroup v2 cgroup to read the limits of
+        assert!(detect(&dir, "12:cpu,cpuacct:/docker/abc\n11:memory:/docker/abc\n").is_none());
+    }
+
+    #[test]
+    fn cpu_usage_starts_at_zero() {
+        let dir = TestDir::new("container-cpu-usage");
+        dir.write("cpu.max", "100000 100000\n");
+        dir.write("cpu.stat", "usage_usec 5000000\nuser_usec 4000000\n");
+        let mut container = detect(&dir, "0::/\n").unwrap();
+        assert_eq!(container.cpu_usage(), Some(0.0));
+    }
+
+    #[test]
+    fn cpu_usage_against_the_quota() {
+        let dir = TestDir::new("container-cpu-delta");
+        dir.write("cpu.max", "200000 100000\n");
+        dir.write("cpu.stat", "usage_usec 5000000\nuser_usec 4000000\n");
+        let mut container = detect(&dir, "0::/\n").unwrap();
+        let start = Instant::now();
+        assert_eq!(container.cpu_usage_at(start), Some(0.0));
+
+        // Half a second of CPU time in a second, a quarter of the two CPUs
+        dir.write("cpu.stat", "usage_usec 5500000\nuser_usec 4400000\n");
+        assert_eq!(container.cpu_usage_at(start + Duration::from_secs(1)), Some(25.0));
+
+        // Bursting above the quota within the period is capped at the limit
+        dir.write("cpu.stat", "usage_usec 8500000\nuser_usec 6000000\n");
+        assert_eq!(container.cpu_usage_at(start + Duration::from_secs(2)), Some(100.0));
+
+        // A read at the same instant has no time to measure over
+        assert_eq!(container.cpu_usage_at(start + Duration::from_secs(2)), Some(0.0));
+    }
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::Path;
+use schema::DiskIo;
+use crate::config::Filter;
+
+const PROC_DISKSTATS: &str = "/proc/diskstats";
+const SYS_CLASS_BLOCK: &str = "/sys/class/block";
+
+/// /proc/diskstats counts sectors of 512 bytes, whatever the sector size of the device
+const SECTOR_SIZE: u64 = 512;
+
+/// Reads the I/O counters of the block devices let through by the filter, matched against their
+/// name with and without /dev/. Partitions are left out as their I/O is part of their disk's, and
+/// so are devices that have never done any I/O, such as unused loop devices.
+/// Systems without /proc/diskstats give an empty list
+pub fn get_disk_io(filter: &Filter) -> Vec<DiskIo> {
+    let contents = match fs::read_to_string(PROC_DISKSTATS) {
+        Ok(contents) => contents,
+        Err(_) => return vec![],
+    };
+    parse_diskstats(&contents)
+        .into_iter()
+        .filter(|disk| disk.reads + disk.writes > 0 && !is_partition(&disk.name))
+        .filter(|disk| filter.allows(&[&disk.name, &format!("/dev/{}", disk.name)]))
+        .collect()
+}
+
+/// Parses the lines of /proc/diskstats, which start with the major and minor number and the name
+/// of the device followed by its counters. Kernels add counters at the end of the line, so only the
+/// first ten are relied on. Lines that don't have them are skipped
+fn parse_diskstats(contents: &str) -> Vec<DiskIo> {
+    contents.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let name = fields.get(2)?;
+            let counter = |index: usize| fields.get(index).and_then(|field| field.parse::<u64>().ok());
+            Some(DiskIo {
+                name: name.to_string(),
+                reads: counter(3)?,
+                read_bytes: counter(5)? * SECTOR_SIZE,
+                read_time: counter(6)?,
+                writes: counter(7)?,
+                written_bytes: counter(9)? * SECTOR_SIZE,
+                write_time: counter(10)?,
+                io_time: counter(12)?,
+            })
+        })
+        .collect()
+}
+
+/// Partitions have a partition attribute in sysfs, where a / in the device name is written as !
+fn is_partition(name: &str) -> bool {
+    Path::new(SYS_CLASS_BLOCK).join(name.replace('/', "!")).join("partition").exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kernels before 4.18 have eleven counters. Before 2.6.25 partitions only had four, which we skip
+    const KERNEL_4_15: &str = "\
+   8       0 sda 58422 1307 3468370 23500 146020 202744 9025312 392012 0 111576 415616
+   8       1 sda1 35 1232 2 16
+";
+
+    /// 4.18 added four discard counters
+    const KERNEL_4_18: &str = "\
+   8       0 sda 58422 1307 3468370 23500 146020 202744 9025312 392012 0 111576 415616 2 0 16 1
+   7       0 loop0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+";
+
+    /// 5.5 added two flush counters
+    const KERNEL_5_5: &str = "\
+ 259       0 nvme0n1 229841 66453 14628934 48823 1096213 1053437 64286514 1166306 0 542520 1283432 0 0 0 0 74613 68302
+ 259       1 nvme0n1p1 341 1062 13350 95 2 0 2 0 0 140 96 0 0 0 0 0 0
+";
+
+    fn sda() -> DiskIo {
+        DiskIo {
+            name: "sda".to_string(),
+            read_bytes: 3468370 * 512,
+            written_bytes: 9025312 * 512,
+            reads: 58422,
+            writes: 146020,
+            read_time: 23500,
+            write_time: 392012,
+            io_time: 111576,
+        }
+    }
+
+    #[test]
+    fn fourteen_fields() {
+        assert_eq!(parse_diskstats(KERNEL_4_15), [sda()]);
+    }
+
+    #[test]
+    fn eighteen_fields() {
+        let disks = parse_diskstats(KERNEL_4_18);
+        assert_eq!(disks.len(), 2);
+        assert_eq!(disks[0], sda());
+        assert_eq!(disks[1], DiskIo { name: "loop0".to_string(), ..Default::default() });
+    }
+
+    #[test]
+    fn twenty_fields() {
+        let disks = parse_diskstats(KERNEL_5_5);
+        assert_eq!(disks[0], DiskIo {
+            name: "nvme0n1".to_string(),
+            read_bytes: 14628934 * 512,
+            written_bytes: 64286514 * 512,
+            reads: 229841,
+            writes: 1096213,
+            read_time: 48823,
+            write_time: 1166306,
+            io_time: 542520,
+        });
+        assert_eq!(disks[1].name, "nvme0n1p1");
+        assert_eq!(disks[1].read_bytes, 13350 * 512);
+        assert_eq!(disks[1].io_time, 140);
+    }
+}
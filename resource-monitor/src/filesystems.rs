@@ -0,0 +1,41 @@
+use std::path::Path;
+
+/// Filesystems that live in memory or are layered over other filesystems. Their space is either
+/// not on any disk or already counted on the disk below, so they are left out of the combined size
+const PSEUDO_FILE_SYSTEMS: [&str; 14] = [
+    "tmpfs", "devtmpfs", "ramfs", "overlay", "aufs", "squashfs", "devfs", "autofs", "nullfs",
+    "efivarfs", "fuse.lxcfs", "fuse.gvfsd-fuse", "fuse.portal", "fuse.snapfuse",
+];
+
+pub fn is_pseudo(file_system: &str) -> bool {
+    PSEUDO_FILE_SYSTEMS.contains(&file_system)
+}
+
+/// The total and used inodes of the filesystem mounted at the path. Filesystems that allocate
+/// inodes as they go, such as btrfs, report none, and so does a mount point that can't be read
+#[cfg(unix)]
+pub fn inodes(mount_point: &Path) -> (u64, u64) {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = CString::new(mount_point.as_os_str().as_bytes()) else {
+        return (0, 0);
+    };
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: the path is NUL terminated and statvfs only writes to the struct it is given
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return (0, 0);
+    }
+    // SAFETY: statvfs filled in the struct as it succeeded
+    let stat = unsafe { stat.assume_init() };
+    // The counts are 32 bits wide on macOS
+    #[allow(clippy::unnecessary_cast)]
+    let (total, free) = (stat.f_files as u64, stat.f_ffree as u64);
+    (total, total.saturating_sub(free))
+}
+
+#[cfg(not(unix))]
+pub fn inodes(_mount_point: &Path) -> (u64, u64) {
+    (0, 0)
+}
@@ -0,0 +1,138 @@
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use schema::{HistorySample, Resources};
+
+/// Takes a timestamped sample of the metrics the dashboard charts
+pub fn sample(resources: &Resources) -> HistorySample {
+    HistorySample {
+        timestamp: unix_millis(),
+        cpu_usage: resources.cpu_usage,
+        used_memory: resources.used_memory,
+        total_memory: resources.total_memory,
+        used_swap: resources.used_swap,
+        total_swap: resources.total_swap,
+        load_avg_one: resources.load_avg_one,
+        bytes_received: resources.bytes_received,
+        bytes_transmitted: resources.bytes_transmitted,
+        disk_read_bytes: resources.disk_io.iter().map(|disk| disk.read_bytes).sum(),
+        disk_written_bytes: resources.disk_io.iter().map(|disk| disk.written_bytes).sum(),
+    }
+}
+
+/// Query parameters accepted by the /history route, e.g. /history?since=1700000000000&fields=cpu_usage,used_memory
+#[derive(Deserialize, Debug, Default)]
+pub struct HistoryQuery {
+    /// Only return samples taken after this unix timestamp in milliseconds
+    pub since: Option<u64>,
+    /// Comma separated list of sample fields to return. The timestamp is always included
+    pub fields: Option<String>,
+}
+
+/// Ring buffer holding the most recent samples, the oldest sample is dropped once it's full
+pub struct History {
+    samples: VecDeque<HistorySample>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, sample: HistorySample) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Returns the samples newer than `since`, oldest first, with only the requested fields
+    pub fn query(&self, query: &HistoryQuery) -> Vec<Value> {
+        let since = query.since.unwrap_or(0);
+        let fields: Option<Vec<&str>> = query.fields.as_ref()
+            .map(|fields| fields.split(',').map(str::trim).collect());
+
+        self.samples.iter()
+            .filter(|sample| sample.timestamp > since)
+            .map(|sample| {
+                let mut object = match serde_json::to_value(sample) {
+                    Ok(Value::Object(object)) => object,
+                    _ => Map::new(),
+                };
+                if let Some(fields) = &fields {
+                    object.retain(|key, _| key == "timestamp" || fields.contains(&key.as_str()));
+                }
+                Value::Object(object)
+            })
+            .collect()
+    }
+}
+
+/// Milliseconds since the unix epoch
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample(timestamp: u64) -> HistorySample {
+        HistorySample { timestamp, cpu_usage: timestamp as f32, used_memory: timestamp * 10, ..Default::default() }
+    }
+
+    fn history(capacity: usize, timestamps: impl IntoIterator<Item = u64>) -> History {
+        let mut history = History::new(capacity);
+        for timestamp in timestamps {
+            history.push(sample(timestamp));
+        }
+        history
+    }
+
+    fn timestamps(samples: &[Value]) -> Vec<u64> {
+        samples.iter().map(|sample| sample["timestamp"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_at_capacity() {
+        let history = history(3, 1..=5);
+        assert_eq!(timestamps(&history.query(&HistoryQuery::default())), [3, 4, 5]);
+    }
+
+    #[test]
+    fn keeps_nothing_without_capacity() {
+        assert!(history(0, 1..=5).query(&HistoryQuery::default()).is_empty());
+    }
+
+    #[test]
+    fn since_is_exclusive() {
+        let history = history(10, [1000, 2000, 3000]);
+        let since = |since| timestamps(&history.query(&HistoryQuery { since: Some(since), fields: None }));
+        assert_eq!(since(2000), [3000]);
+        assert_eq!(since(1999), [2000, 3000]);
+        assert!(since(3000).is_empty());
+    }
+
+    #[test]
+    fn only_the_requested_fields() {
+        let history = history(10, [1000, 2000]);
+        let query = HistoryQuery { since: Some(1000), fields: Some(String::from("cpu_usage, used_memory,unknown")) };
+        assert_eq!(history.query(&query), [json!({"timestamp": 2000, "cpu_usage": 2000.0, "used_memory": 20000})]);
+
+        // Every field without the parameter
+        let all = history.query(&HistoryQuery::default());
+        assert_eq!(all[0], serde_json::to_value(sample(1000)).unwrap());
+    }
+}
@@ -0,0 +1,16 @@
+//! The collectors of the agent and its settings, a library of their own so the benchmarks can
+//! drive the same refreshes as the agent does
+pub mod args;
+pub mod config;
+pub mod resources;
+pub mod processes;
+mod sensors;
+mod networks;
+mod diskstats;
+mod filesystems;
+mod meminfo;
+mod pressure;
+mod cgroups;
+mod container;
+#[cfg(test)]
+mod test_dir;
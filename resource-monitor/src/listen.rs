@@ -0,0 +1,67 @@
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use axum::Router;
+use tokio::task::JoinSet;
+use crate::config::UnixSocket;
+
+/// Binds every address up front, so an address that is taken is reported before anything is served
+pub fn bind_tcp(addresses: &[IpAddr], port: u16) -> Result<Vec<TcpListener>, String> {
+    addresses.iter()
+        .map(|address| {
+            let addr = SocketAddr::new(*address, port);
+            let listener = TcpListener::bind(addr)
+                .map_err(|err| format!("Failed to listen on {}: {}", addr, err))?;
+            listener.set_nonblocking(true)
+                .map_err(|err| format!("Failed to listen on {}: {}", addr, err))?;
+            Ok(listener)
+        })
+        .collect()
+}
+
+/// The servers of all listeners, the agent stops when any of them does
+pub type Servers = JoinSet<Result<(), String>>;
+
+/// Binds the Unix socket, if there is one, and serves the routes on it. A socket left behind by an
+/// agent that didn't shut down cleanly is replaced, but one another agent is still listening on is not
+#[cfg(unix)]
+pub fn serve_unix(socket: &UnixSocket, app: Router, servers: &mut Servers) -> Result<(), String> {
+    use std::{fs, io};
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use hyper::server::accept;
+    use tokio::net::UnixListener;
+    use tokio_stream::wrappers::UnixListenerStream;
+
+    let path = match &socket.path {
+        Some(path) => path.clone(),
+        None => return Ok(()),
+    };
+    let failed = |err: io::Error| format!("Failed to listen on {}: {}", path.display(), err);
+    if let Ok(metadata) = fs::symlink_metadata(&path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("Failed to listen on {}: the file exists and isn't a socket", path.display()));
+        }
+        if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+            return Err(format!("Failed to listen on {}: another process is listening on it", path.display()));
+        }
+        fs::remove_file(&path).map_err(failed)?;
+    }
+    let listener = UnixListener::bind(&path).map_err(failed)?;
+    if let Some(mode) = socket.permissions() {
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).map_err(failed)?;
+    }
+
+    servers.spawn(async move {
+        axum::Server::builder(accept::from_stream(UnixListenerStream::new(listener)))
+            .serve(app.into_make_service())
+            .await
+            .map_err(|err| format!("Serving on {} failed: {}", path.display(), err))
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve_unix(socket: &UnixSocket, _app: Router, _servers: &mut Servers) -> Result<(), String> {
+    match socket.path {
+        Some(_) => Err("Unix sockets aren't supported on this platform".to_string()),
+        None => Ok(()),
+    }
+}
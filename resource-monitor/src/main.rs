@@ -1,57 +1,241 @@
-mod resources;
-mod args;
+mod metrics;
+mod history;
+mod auth;
+mod tls;
+mod listen;
 
-use crate::resources::{Resources};
-use axum::{routing::get, Router};
+use resource_monitor::{args, config};
+use resource_monitor::resources::Resources;
+use resource_monitor::processes::ProcessQuery;
+use crate::history::{History, HistoryQuery};
+use axum::{middleware, routing::get, Router};
+use axum::extract::{FromRef, Query, State};
+use axum::response::Response;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::{Arc};
-use std::time::Duration;
+use std::time::Instant;
 use axum::http::header::CONTENT_TYPE;
 use clap::Parser;
-use tokio::sync::Mutex;
-use tokio::time::sleep;
+use tokio::sync::{broadcast, Mutex};
+use tokio::time::sleep_until;
+use tokio_stream::{Stream, StreamExt};
+use tokio_stream::wrappers::BroadcastStream;
+use axum_server::tls_rustls::RustlsConfig;
 use args::Args;
+use config::Config;
+use listen::Servers;
+use tls::TlsFiles;
+
+type SharedResources = Arc<Mutex<Resources>>;
+type SharedHistory = Arc<Mutex<History>>;
+type Updates = broadcast::Sender<String>;
+
+/// How many serialized updates a slow subscriber can fall behind before it starts skipping them
+const UPDATE_CHANNEL_CAPACITY: usize = 16;
+
+/// State shared between the refresh loop and the route handlers
+#[derive(Clone)]
+struct AppState {
+    resources: SharedResources,
+    history: SharedHistory,
+    updates: Updates,
+}
+
+impl FromRef<AppState> for SharedResources {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.resources)
+    }
+}
+
+impl FromRef<AppState> for SharedHistory {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.history)
+    }
+}
+
+impl FromRef<AppState> for Updates {
+    fn from_ref(state: &AppState) -> Self {
+        state.updates.clone()
+    }
+}
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
-    let server_resources = Arc::new(Mutex::new(Resources::new()));
+    // Everything that can be wrong with the settings is reported before we start serving
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    if args.print_config {
+        print!("{}", config.to_toml());
+        return;
+    }
+    let token = match auth::load_token(&config.auth) {
+        Ok(token) => token,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let state = AppState {
+        resources: Arc::new(Mutex::new(Resources::new(&config))),
+        history: Arc::new(Mutex::new(History::new(config.history_length))),
+        updates: broadcast::channel(UPDATE_CHANNEL_CAPACITY).0,
+    };
 
-    tokio::spawn(refresh_loop(Arc::clone(&server_resources), args.update_frequency));
+    tokio::spawn(refresh_loop(state.clone()));
 
-    let app = Router::new().route("/resources", get(move || {
-        let resource_mutex = Arc::clone(&server_resources);
-        async move {
-            let resource = resource_mutex.lock().await;
-            match resource.serialize() {
-                Ok(json_response) => {
-                    axum::http::Response::builder()
-                        .header(CONTENT_TYPE, "application/json")
-                        .body(json_response)
-                        .unwrap()
-                }
-                Err(err) => {
-                    eprintln!("Error serializing resource: {:?}", err);
-                    axum::http::Response::builder()
-                        .status(500)
-                        .body("Internal Server Error".to_string())
-                        .unwrap()
-                }
+    let mut app = Router::new()
+        .route("/resources", get(resources_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/history", get(history_handler))
+        .route("/stream", get(stream_handler));
+    // Without the collector there are no processes to list, so the route is left out
+    if config.collectors.processes {
+        app = app.route("/processes", get(processes_handler));
+    }
+    if let Some(token) = token {
+        app = app.layer(middleware::from_fn_with_state(Arc::new(token), auth::require_token));
+    }
+    let app = app.with_state(state);
+
+    let mut servers = Servers::new();
+    if let Err(err) = serve(&config, app, &mut servers) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    // The servers only stop when something went wrong
+    if let Some(result) = servers.join_next().await {
+        match result {
+            Ok(Err(err)) => eprintln!("{}", err),
+            Err(err) => eprintln!("A server stopped unexpectedly: {}", err),
+            Ok(Ok(())) => {}
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Listens on every bind address, over HTTPS if a certificate is configured, and on the Unix socket.
+/// The socket is served plain HTTP, it is only reachable from the host itself
+fn serve(config: &Config, app: Router, servers: &mut Servers) -> Result<(), String> {
+    let listeners = listen::bind_tcp(&config.bind, config.port)?;
+    listen::serve_unix(&config.unix_socket, app.clone(), servers)?;
+
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    match TlsFiles::from_config(&config.tls) {
+        Some(tls_files) => {
+            let tls_config = RustlsConfig::from_config(Arc::new(tls_files.server_config()?));
+            tokio::spawn(tls_files.reload_on_hangup(tls_config.clone()));
+            for listener in listeners {
+                let addr = listener.local_addr().map_err(|err| err.to_string())?;
+                let server = axum_server::from_tcp_rustls(listener, tls_config.clone()).serve(service.clone());
+                servers.spawn(async move {
+                    server.await.map_err(|err| format!("Serving on {} failed: {}", addr, err))
+                });
+            }
+        }
+        None => {
+            for listener in listeners {
+                let addr = listener.local_addr().map_err(|err| err.to_string())?;
+                let server = axum::Server::from_tcp(listener)
+                    .map_err(|err| format!("Failed to listen on {}: {}", addr, err))?
+                    .serve(service.clone());
+                servers.spawn(async move {
+                    server.await.map_err(|err| format!("Serving on {} failed: {}", addr, err))
+                });
             }
         }
-    }));
+    }
+    Ok(())
+}
+
+async fn resources_handler(State(resources): State<SharedResources>) -> Response<String> {
+    let resource = resources.lock().await;
+    json_response(resource.serialize())
+}
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], args.port));
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+/// Lists running processes. Supports ?sort=cpu|mem|pid|name&limit=N to only fetch the top N processes
+async fn processes_handler(State(resources): State<SharedResources>,
+                           Query(query): Query<ProcessQuery>) -> Response<String> {
+    let resource = resources.lock().await;
+    json_response(serde_json::to_string(&resource.processes(&query)))
 }
 
-async fn refresh_loop(resources: Arc<Mutex<Resources>>, update_frequency: u64) {
+/// Exposes the resources in the Prometheus text format so the agent can be scraped directly
+async fn metrics_handler(State(resources): State<SharedResources>) -> Response<String> {
+    let resource = resources.lock().await;
+    Response::builder()
+        .header(CONTENT_TYPE, metrics::PROMETHEUS_CONTENT_TYPE)
+        .body(metrics::render(resource.snapshot()))
+        .unwrap()
+}
+
+/// Returns the recorded samples. Supports ?since=<unix_ms>&fields=field1,field2 so a client
+/// can fetch only what it is missing
+async fn history_handler(State(history): State<SharedHistory>,
+                         Query(query): Query<HistoryQuery>) -> Response<String> {
+    let history = history.lock().await;
+    json_response(serde_json::to_string(&history.query(&query)))
+}
+
+/// Pushes the resources as a Server-Sent Event every time they are refreshed.
+/// The current resources are sent right away so subscribers don't have to wait for the next refresh
+async fn stream_handler(State(resources): State<SharedResources>,
+                        State(updates): State<Updates>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // Subscribe before serializing so no refresh falls between the two
+    let receiver = updates.subscribe();
+    let current = resources.lock().await.serialize().ok();
+
+    let refreshes = BroadcastStream::new(receiver).filter_map(|update| update.ok());
+    let stream = tokio_stream::iter(current)
+        .chain(refreshes)
+        .map(|json| Ok(Event::default().data(json)));
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Builds a JSON response from the serialized body, or a 500 if serialization failed
+fn json_response(serialized: Result<String, serde_json::Error>) -> Response<String> {
+    match serialized {
+        Ok(json_response) => {
+            Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .body(json_response)
+                .unwrap()
+        }
+        Err(err) => {
+            eprintln!("Error serializing resource: {:?}", err);
+            Response::builder()
+                .status(500)
+                .body("Internal Server Error".to_string())
+                .unwrap()
+        }
+    }
+}
+
+/// Refreshes the collectors as they are due. Every update_frequency, however many collectors were refreshed
+/// in between, a sample of the resources is recorded in the history and pushed to the stream subscribers
+async fn refresh_loop(state: AppState) {
     loop {
-        sleep(Duration::from_millis(update_frequency)).await;
-        let mut resource = resources.lock().await;
-        resource.refresh();
+        let next_refresh = state.resources.lock().await.next_refresh();
+        sleep_until(next_refresh.into()).await;
+        let mut resource = state.resources.lock().await;
+        if !resource.refresh(Instant::now()) {
+            continue;
+        }
+        state.history.lock().await.push(history::sample(resource.snapshot()));
+
+        if state.updates.receiver_count() > 0 {
+            match resource.serialize() {
+                // Sending only fails when every subscriber disconnected in the meantime
+                Ok(json) => { let _ = state.updates.send(json); }
+                Err(err) => eprintln!("Error serializing resource: {:?}", err),
+            }
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::fs;
+use schema::MemoryBreakdown;
+
+const PROC_MEMINFO: &str = "/proc/meminfo";
+const PROC_VMSTAT: &str = "/proc/vmstat";
+
+/// Reads the breakdown of memory from /proc/meminfo and how much has been swapped from /proc/vmstat.
+/// Systems without /proc/meminfo give None
+pub fn get_memory_breakdown() -> Option<MemoryBreakdown> {
+    let meminfo = fs::read_to_string(PROC_MEMINFO).ok()?;
+    // Without /proc/vmstat nothing is known to have been swapped
+    let vmstat = fs::read_to_string(PROC_VMSTAT).unwrap_or_default();
+    Some(breakdown(&meminfo, &vmstat, page_size()))
+}
+
+/// The breakdown from the contents of /proc/meminfo and /proc/vmstat. Values that are missing count as 0
+fn breakdown(meminfo: &str, vmstat: &str, page_size: u64) -> MemoryBreakdown {
+    let meminfo = parse_meminfo(meminfo);
+    let vmstat = parse_vmstat(vmstat);
+    let bytes = |key: &str| meminfo.get(key).copied().unwrap_or(0);
+    let swapped = |key: &str| vmstat.get(key).copied().unwrap_or(0) * page_size;
+    MemoryBreakdown {
+        available: bytes("MemAvailable"),
+        free: bytes("MemFree"),
+        buffers: bytes("Buffers"),
+        // Counted the way free does, with the kernel caches that can be reclaimed
+        cached: bytes("Cached") + bytes("SReclaimable"),
+        shared: bytes("Shmem"),
+        dirty: bytes("Dirty"),
+        swapped_in: swapped("pswpin"),
+        swapped_out: swapped("pswpout"),
+    }
+}
+
+/// Parses lines such as `MemFree:  398020 kB` into bytes. Values without a unit are counts and kept as they are
+fn parse_meminfo(contents: &str) -> HashMap<&str, u64> {
+    contents.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let mut fields = value.split_whitespace();
+            let value: u64 = fields.next()?.parse().ok()?;
+            match fields.next() {
+                Some("kB") => Some((key, value * 1024)),
+                _ => Some((key, value)),
+            }
+        })
+        .collect()
+}
+
+/// Parses lines such as `pswpin 12`
+fn parse_vmstat(contents: &str) -> HashMap<&str, u64> {
+    contents.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            Some((key, value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// The swap counters of /proc/vmstat are in pages
+#[cfg(unix)]
+fn page_size() -> u64 {
+    // SAFETY: sysconf only reads a system setting
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as u64,
+        _ => 4096,
+    }
+}
+
+#[cfg(not(unix))]
+fn page_size() -> u64 {
+    4096
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The start of /proc/meminfo on a 5.15 kernel
+    const MEMINFO: &str = "\
+MemTotal:        8024616 kB
+MemFree:          398020 kB
+MemAvailable:    5201828 kB
+Buffers:          250612 kB
+Cached:          4330036 kB
+SwapCached:         1032 kB
+Active:          3662144 kB
+Inactive:        3075772 kB
+Dirty:               784 kB
+Writeback:             0 kB
+Shmem:            301340 kB
+SReclaimable:     390428 kB
+HugePages_Total:       0
+Hugepagesize:       2048 kB
+";
+
+    /// Some of the lines of /proc/vmstat
+    const VMSTAT: &str = "\
+nr_free_pages 99505
+nr_dirty 196
+pgpgin 20413513
+pgpgout 49726836
+pswpin 1207
+pswpout 5931
+";
+
+    #[test]
+    fn meminfo_in_bytes() {
+        let meminfo = parse_meminfo(MEMINFO);
+        assert_eq!(meminfo["MemFree"], 398_020 * 1024);
+        assert_eq!(meminfo["Writeback"], 0);
+        // Counts without a unit are kept as they are
+        assert_eq!(meminfo["HugePages_Total"], 0);
+        assert_eq!(meminfo["Hugepagesize"], 2048 * 1024);
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let meminfo = parse_meminfo("MemFree: lots kB\nBuffers:\nCached 12 kB\nDirty:   784 kB\n");
+        assert_eq!(meminfo.len(), 1);
+        assert_eq!(meminfo["Dirty"], 784 * 1024);
+
+        let vmstat = parse_vmstat("pswpin\npswpout many\nnr_dirty 196\n");
+        assert_eq!(vmstat.len(), 1);
+        assert_eq!(vmstat["nr_dirty"], 196);
+    }
+
+    #[test]
+    fn breakdown_of_the_samples() {
+        assert_eq!(breakdown(MEMINFO, VMSTAT, 4096), MemoryBreakdown {
+            available: 5_201_828 * 1024,
+            free: 398_020 * 1024,
+            buffers: 250_612 * 1024,
+            cached: (4_330_036 + 390_428) * 1024,
+            shared: 301_340 * 1024,
+            dirty: 784 * 1024,
+            swapped_in: 1207 * 4096,
+            swapped_out: 5931 * 4096,
+        });
+    }
+
+    #[test]
+    fn breakdown_without_vmstat() {
+        let memory = breakdown(MEMINFO, "", 4096);
+        assert_eq!((memory.swapped_in, memory.swapped_out), (0, 0));
+        assert_eq!(memory.free, 398_020 * 1024);
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use schema::{Cgroup, DiskIo, NetworkInterface, PressureStall, Resources};
+
+/// Content type of the Prometheus text exposition format
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const PREFIX: &str = "resource_monitor";
+
+/// Metric name, help text and a getter for one of the per-interface counters
+type InterfaceCounter = (&'static str, &'static str, fn(&NetworkInterface) -> u64);
+
+/// Metric name, help text and a getter for one of the per-device I/O counters, as a float so times can be in seconds
+type DiskIoCounter = (&'static str, &'static str, fn(&DiskIo) -> f64);
+
+/// Metric name, help text, type and a getter for one of the per-cgroup values
+type CgroupMetric = (&'static str, &'static str, MetricType, fn(&Cgroup) -> f64);
+
+#[derive(Clone, Copy)]
+enum MetricType {
+    Gauge,
+    Counter,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+        }
+    }
+}
+
+/// Builds a Prometheus text exposition one metric family at a time.
+/// Every sample gets the hostname label so scrapes of several agents can be told apart
+struct Exposition {
+    output: String,
+    hostname: String,
+}
+
+impl Exposition {
+    fn new(hostname: &str) -> Self {
+        Exposition {
+            output: String::new(),
+            hostname: escape_label_value(hostname),
+        }
+    }
+
+    /// Writes the HELP and TYPE lines followed by one sample per entry in `samples`
+    fn family<'a, I>(&mut self, name: &str, help: &str, metric_type: MetricType, samples: I)
+        where I: IntoIterator<Item = (Vec<(&'a str, &'a str)>, f64)>
+    {
+        let name = format!("{}_{}", PREFIX, name);
+        writeln!(self.output, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.output, "# TYPE {} {}", name, metric_type.as_str()).unwrap();
+        for (labels, value) in samples {
+            let mut label_string = format!("hostname=\"{}\"", self.hostname);
+            for (key, label_value) in labels {
+                write!(label_string, ",{}=\"{}\"", key, escape_label_value(label_value)).unwrap();
+            }
+            writeln!(self.output, "{}{{{}}} {}", name, label_string, format_value(value)).unwrap();
+        }
+    }
+
+    /// Shorthand for a metric family with a single unlabelled sample
+    fn single(&mut self, name: &str, help: &str, metric_type: MetricType, value: f64) {
+        self.family(name, help, metric_type, [(vec![], value)]);
+    }
+}
+
+/// Backslashes, double quotes and newlines have to be escaped in label values
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Prometheus spells the values that aren't numbers +Inf, -Inf and NaN, which Rust writes differently
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Indices of the disks to export. A device mounted in two places is two disks, but the same
+/// device listed twice at the same mount point would give duplicate series, so only the first is kept
+fn unique_disks(resources: &Resources) -> Vec<usize> {
+    let disks: Vec<(&String, &String)> = resources.disk_names.iter().zip(&resources.disk_mount_points).collect();
+    (0..disks.len()).filter(|&index| !disks[..index].contains(&disks[index])).collect()
+}
+
+/// Sensor labels with a number appended to repeats, such as two NVMe drives that both report
+/// "nvme Composite", as series with the same labels make Prometheus reject the whole scrape
+fn unique_sensor_labels(resources: &Resources) -> Vec<String> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    resources.sensors.iter()
+        .map(|sensor| {
+            let count = seen.entry(sensor.label.as_str()).or_insert(0);
+            *count += 1;
+            if *count == 1 { sensor.label.clone() } else { format!("{} #{}", sensor.label, count) }
+        })
+        .collect()
+}
+
+/// Renders every field of Resources in the Prometheus text exposition format
+pub fn render(resources: &Resources) -> String {
+    use MetricType::{Counter, Gauge};
+    let mut exposition = Exposition::new(&resources.hostname);
+
+    exposition.family("info", "Operating system and kernel version of the host", Gauge,
+                      [(vec![("os_version", resources.os_version.as_str()),
+                             ("kernel_version", resources.kernel_version.as_str())], 1.0)]);
+    exposition.single("uptime_seconds", "Time since the host booted", Gauge, resources.uptime as f64);
+
+    exposition.single("memory_total_bytes", "Total amount of memory", Gauge, resources.total_memory as f64);
+    exposition.single("memory_used_bytes", "Amount of memory in use", Gauge, resources.used_memory as f64);
+    if let Some(limits) = &resources.limits {
+        if let Some(cpus) = limits.cpus {
+            exposition.single("limit_cpus", "CPUs the agent's cgroup may use", Gauge, cpus);
+        }
+        if let Some(memory) = limits.memory {
+            exposition.single("limit_memory_bytes", "Memory the agent's cgroup may use", Gauge, memory as f64);
+        }
+    }
+    exposition.single("swap_total_bytes", "Total amount of swap", Gauge, resources.total_swap as f64);
+    exposition.single("swap_used_bytes", "Amount of swap in use", Gauge, resources.used_swap as f64);
+    if let Some(memory) = &resources.memory {
+        exposition.single("memory_available_bytes", "Memory that can be allocated without swapping", Gauge, memory.available as f64);
+        exposition.single("memory_free_bytes", "Memory not used for anything", Gauge, memory.free as f64);
+        exposition.single("memory_buffers_bytes", "Memory used by block device buffers", Gauge, memory.buffers as f64);
+        exposition.single("memory_cached_bytes", "Memory used by the page cache and reclaimable kernel caches", Gauge,
+                          memory.cached as f64);
+        exposition.single("memory_shared_bytes", "Memory used by tmpfs and shared memory", Gauge, memory.shared as f64);
+        exposition.single("memory_dirty_bytes", "Memory waiting to be written back to disk", Gauge, memory.dirty as f64);
+        exposition.single("swap_in_bytes_total", "Bytes swapped in since boot", Counter, memory.swapped_in as f64);
+        exposition.single("swap_out_bytes_total", "Bytes swapped out since boot", Counter, memory.swapped_out as f64);
+    }
+
+    exposition.single("cpu_count", "Number of logical CPU cores", Gauge, resources.cpu_amount as f64);
+    exposition.single("cpu_usage_percent", "Usage of all CPU cores combined", Gauge, resources.cpu_usage as f64);
+    let cores: Vec<String> = (0..resources.cpu_load_per_core.len()).map(|core| core.to_string()).collect();
+    exposition.family("cpu_core_usage_percent", "Usage of each logical CPU core", Gauge,
+                      resources.cpu_load_per_core.iter().zip(&cores)
+                          .map(|(usage, core)| (vec![("core", core.as_str())], *usage as f64)));
+
+    exposition.single("load_average_1m", "Load average over the last minute", Gauge, resources.load_avg_one);
+    exposition.single("load_average_5m", "Load average over the last five minutes", Gauge, resources.load_avg_five);
+    exposition.single("load_average_15m", "Load average over the last fifteen minutes", Gauge, resources.load_avg_fifteen);
+
+    exposition.single("filesystem_total_bytes", "Combined size of all disks, without pseudo filesystems", Gauge, resources.total_space as f64);
+    exposition.single("filesystem_available_bytes", "Combined available space of all disks, without pseudo filesystems", Gauge,
+                      resources.available_space as f64);
+    let disks = unique_disks(resources);
+    let disk_labels = |index: usize| vec![("disk", resources.disk_names[index].as_str()),
+                                          ("mount_point", resources.disk_mount_points[index].as_str())];
+    let disk_values = |values: &[u64]| disks.iter()
+        .filter_map(|&index| values.get(index).map(|value| (disk_labels(index), *value as f64)))
+        .collect::<Vec<_>>();
+    exposition.family("disk_total_bytes", "Size of each disk", Gauge, disk_values(&resources.disk_total));
+    exposition.family("disk_available_bytes", "Available space on each disk", Gauge,
+                      disk_values(&resources.disk_available));
+    exposition.family("disk_inodes_total", "Inodes on each disk, 0 where they are allocated as needed", Gauge,
+                      disk_values(&resources.disk_inodes_total));
+    exposition.family("disk_inodes_used", "Inodes in use on each disk", Gauge,
+                      disk_values(&resources.disk_inodes_used));
+    // The filesystem type and removable flag as labels, so they can be joined onto the other disk metrics
+    exposition.family("disk_info", "Filesystem type and removable flag of each disk, always 1", Gauge,
+                      disks.iter().filter_map(|&index| {
+                          let file_system = resources.disk_file_systems.get(index)?;
+                          let removable = resources.disk_removable.get(index)?;
+                          let mut labels = disk_labels(index);
+                          labels.push(("file_system", file_system.as_str()));
+                          labels.push(("removable", if *removable { "true" } else { "false" }));
+                          Some((labels, 1.0))
+                      }));
+
+    let device_labels: Vec<Vec<(&str, &str)>> = resources.disk_io.iter()
+        .map(|disk| vec![("device", disk.name.as_str())])
+        .collect();
+    let disk_io_counters: [DiskIoCounter; 7] = [
+        ("disk_read_bytes_total", "Bytes read from each block device", |d| d.read_bytes as f64),
+        ("disk_written_bytes_total", "Bytes written to each block device", |d| d.written_bytes as f64),
+        ("disk_reads_completed_total", "Reads completed by each block device", |d| d.reads as f64),
+        ("disk_writes_completed_total", "Writes completed by each block device", |d| d.writes as f64),
+        ("disk_read_time_seconds_total", "Time spent reading by each block device", |d| d.read_time as f64 / 1000.0),
+        ("disk_write_time_seconds_total", "Time spent writing by each block device", |d| d.write_time as f64 / 1000.0),
+        ("disk_io_time_seconds_total", "Time each block device was busy doing I/O", |d| d.io_time as f64 / 1000.0),
+    ];
+    for (name, help, counter) in disk_io_counters {
+        exposition.family(name, help, Counter,
+                          device_labels.iter().cloned().zip(&resources.disk_io)
+                              .map(|(labels, disk)| (labels, counter(disk))));
+    }
+
+    if let Some(pressure) = &resources.pressure {
+        let stalls: Vec<(&str, &str, &PressureStall)> = [("cpu", &pressure.cpu), ("memory", &pressure.memory), ("io", &pressure.io)]
+            .into_iter()
+            .flat_map(|(resource, stalls)| {
+                std::iter::once(("some", &stalls.some)).chain(stalls.full.as_ref().map(|full| ("full", full)))
+                    .map(move |(kind, stall)| (resource, kind, stall))
+            })
+            .collect();
+        exposition.family("pressure_stalled_seconds_total", "Time tasks were stalled waiting for each resource", Counter,
+                          stalls.iter().map(|(resource, kind, stall)| {
+                              (vec![("resource", *resource), ("kind", *kind)], stall.total as f64 / 1_000_000.0)
+                          }));
+        exposition.family("pressure_stalled_percent", "Share of the time tasks were stalled over the window", Gauge,
+                          stalls.iter().flat_map(|(resource, kind, stall)| {
+                              [("10s", stall.avg10), ("60s", stall.avg60), ("300s", stall.avg300)].into_iter()
+                                  .map(move |(window, average)| {
+                                      (vec![("resource", *resource), ("kind", *kind), ("window", window)], average as f64)
+                                  })
+                          }));
+    }
+
+    let cgroup_labels: Vec<Vec<(&str, &str)>> = resources.cgroups.iter()
+        .map(|cgroup| vec![("cgroup", cgroup.path.as_str())])
+        .collect();
+    let cgroup_metrics: [CgroupMetric; 5] = [
+        ("cgroup_cpu_seconds_total", "CPU time used by each cgroup", Counter, |c| c.cpu_time as f64 / 1_000_000.0),
+        ("cgroup_memory_bytes", "Memory used by each cgroup", Gauge, |c| c.memory_current as f64),
+        ("cgroup_io_read_bytes_total", "Bytes read by each cgroup", Counter, |c| c.io_read_bytes as f64),
+        ("cgroup_io_written_bytes_total", "Bytes written by each cgroup", Counter, |c| c.io_written_bytes as f64),
+        ("cgroup_pids", "Processes and threads in each cgroup", Gauge, |c| c.pids as f64),
+    ];
+    for (name, help, metric_type, value) in cgroup_metrics {
+        exposition.family(name, help, metric_type,
+                          cgroup_labels.iter().cloned().zip(&resources.cgroups)
+                              .map(|(labels, cgroup)| (labels, value(cgroup))));
+    }
+    // Only the cgroups that have a limit
+    exposition.family("cgroup_memory_max_bytes", "Memory limit of each cgroup", Gauge,
+                      cgroup_labels.iter().cloned().zip(&resources.cgroups)
+                          .filter_map(|(labels, cgroup)| Some((labels, cgroup.memory_max? as f64))));
+
+    exposition.single("network_received_bytes_total", "Bytes received on all network interfaces", Counter,
+                      resources.bytes_received as f64);
+    exposition.single("network_transmitted_bytes_total", "Bytes transmitted on all network interfaces", Counter,
+                      resources.bytes_transmitted as f64);
+
+    let interface_labels: Vec<Vec<(&str, &str)>> = resources.networks.iter()
+        .map(|interface| vec![("interface", interface.name.as_str())])
+        .collect();
+    let interface_counters: [InterfaceCounter; 8] = [
+        ("network_interface_received_bytes_total", "Bytes received on each network interface", |i| i.received),
+        ("network_interface_transmitted_bytes_total", "Bytes transmitted on each network interface", |i| i.transmitted),
+        ("network_interface_received_packets_total", "Packets received on each network interface", |i| i.packets_received),
+        ("network_interface_transmitted_packets_total", "Packets transmitted on each network interface", |i| i.packets_transmitted),
+        ("network_interface_receive_errors_total", "Receive errors on each network interface", |i| i.errors_received),
+        ("network_interface_transmit_errors_total", "Transmit errors on each network interface", |i| i.errors_transmitted),
+        ("network_interface_receive_drops_total", "Dropped incoming packets on each network interface", |i| i.drops_received),
+        ("network_interface_transmit_drops_total", "Dropped outgoing packets on each network interface", |i| i.drops_transmitted),
+    ];
+    for (name, help, counter) in interface_counters {
+        exposition.family(name, help, Counter,
+                          interface_labels.iter().cloned().zip(&resources.networks)
+                              .map(|(labels, interface)| (labels, counter(interface) as f64)));
+    }
+
+    let sensor_names = unique_sensor_labels(resources);
+    let sensor_labels: Vec<Vec<(&str, &str)>> = sensor_names.iter()
+        .map(|name| vec![("sensor", name.as_str())])
+        .collect();
+    exposition.family("temperature_celsius", "Current temperature of each hardware sensor", Gauge,
+                      sensor_labels.iter().cloned().zip(&resources.sensors)
+                          .map(|(labels, sensor)| (labels, sensor.temperature as f64)));
+    exposition.family("temperature_max_celsius", "Highest temperature seen by each hardware sensor", Gauge,
+                      sensor_labels.iter().cloned().zip(&resources.sensors)
+                          .filter_map(|(labels, sensor)| sensor.max.map(|max| (labels, max as f64))));
+    exposition.family("temperature_critical_celsius", "Critical temperature threshold of each hardware sensor", Gauge,
+                      sensor_labels.iter().cloned().zip(&resources.sensors)
+                          .filter_map(|(labels, sensor)| sensor.critical.map(|critical| (labels, critical as f64))));
+
+    exposition.output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schema::Sensor;
+
+    /// Label sets of the samples of one metric family, without the hostname
+    fn series<'a>(output: &'a str, name: &str) -> Vec<&'a str> {
+        let prefix = format!("{}_{}{{", PREFIX, name);
+        output.lines()
+            .filter_map(|line| line.strip_prefix(prefix.as_str()))
+            .map(|line| line.split_once('}').unwrap().0)
+            .collect()
+    }
+
+    fn sensor(label: &str, temperature: f32) -> Sensor {
+        Sensor { label: label.to_string(), temperature, ..Default::default() }
+    }
+
+    #[test]
+    fn repeated_sensor_labels_get_numbered() {
+        let resources = Resources {
+            hostname: "host".to_string(),
+            sensors: vec![sensor("nvme Composite", 40.0), sensor("acpitz", 30.0),
+                          sensor("nvme Composite", 45.0), sensor("nvme Composite", 50.0)],
+            ..Default::default()
+        };
+        let output = render(&resources);
+        assert_eq!(series(&output, "temperature_celsius"), [
+            "hostname=\"host\",sensor=\"nvme Composite\"",
+            "hostname=\"host\",sensor=\"acpitz\"",
+            "hostname=\"host\",sensor=\"nvme Composite #2\"",
+            "hostname=\"host\",sensor=\"nvme Composite #3\"",
+        ]);
+    }
+
+    #[test]
+    fn same_disk_at_same_mount_point_is_exported_once() {
+        let resources = Resources {
+            hostname: "host".to_string(),
+            disk_names: vec!["sda1".into(), "sda1".into(), "sda1".into()],
+            disk_mount_points: vec!["/".into(), "/srv".into(), "/".into()],
+            disk_total: vec![100, 100, 100],
+            disk_available: vec![50, 50, 50],
+            disk_inodes_total: vec![10, 10, 10],
+            disk_inodes_used: vec![5, 5, 5],
+            disk_file_systems: vec!["ext4".into(), "ext4".into(), "ext4".into()],
+            disk_removable: vec![false, false, false],
+            ..Default::default()
+        };
+        let output = render(&resources);
+        let expected = [
+            "hostname=\"host\",disk=\"sda1\",mount_point=\"/\"",
+            "hostname=\"host\",disk=\"sda1\",mount_point=\"/srv\"",
+        ];
+        for name in ["disk_total_bytes", "disk_available_bytes", "disk_inodes_total", "disk_inodes_used"] {
+            assert_eq!(series(&output, name), expected, "{}", name);
+        }
+        assert_eq!(series(&output, "disk_info").len(), 2);
+    }
+
+    #[test]
+    fn values_that_are_not_numbers() {
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(42.5), "42.5");
+        assert_eq!(format_value(3.0), "3");
+
+        // A sensor without a reading yet
+        let resources = Resources { hostname: "host".to_string(), sensors: vec![sensor("acpitz", f32::NAN)], ..Default::default() };
+        assert!(render(&resources).contains("resource_monitor_temperature_celsius{hostname=\"host\",sensor=\"acpitz\"} NaN\n"));
+    }
+}
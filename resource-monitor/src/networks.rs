@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+use schema::NetworkInterface;
+use sysinfo::{NetworkExt, System, SystemExt};
+use crate::config::Filter;
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// Name prefixes of interfaces created by container runtimes, hypervisors and VPNs.
+/// Only used where we can't ask sysfs whether the interface is backed by a device
+const VIRTUAL_PREFIXES: [&str; 14] = [
+    "docker", "br-", "veth", "virbr", "vnet", "tun", "tap", "cni", "flannel", "cali", "wg", "utun", "bridge", "awdl",
+];
+
+/// Iterates over the interfaces let through by the filter and returns the counters of each, sorted by name
+pub fn get_network_interfaces(sys: &System, filter: &Filter) -> Vec<NetworkInterface> {
+    let mut interfaces: Vec<NetworkInterface> = sys.networks()
+        .into_iter()
+        .filter(|(name, _)| filter.allows(&[name]))
+        .map(|(name, network)| NetworkInterface {
+            name: name.clone(),
+            received: network.total_received(),
+            transmitted: network.total_transmitted(),
+            packets_received: network.total_packets_received(),
+            packets_transmitted: network.total_packets_transmitted(),
+            errors_received: network.total_errors_on_received(),
+            errors_transmitted: network.total_errors_on_transmitted(),
+            drops_received: read_statistic(name, "rx_dropped"),
+            drops_transmitted: read_statistic(name, "tx_dropped"),
+            is_loopback: is_loopback(name),
+            is_virtual: is_virtual(name),
+        })
+        .collect();
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    interfaces
+}
+
+/// sysinfo doesn't report dropped packets so we read them from sysfs where available
+fn read_statistic(interface: &str, statistic: &str) -> u64 {
+    let path = Path::new(SYS_CLASS_NET).join(interface).join("statistics").join(statistic);
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn is_loopback(interface: &str) -> bool {
+    interface == "lo" || interface.starts_with("lo0")
+}
+
+/// On Linux an interface backed by hardware has a device link in sysfs.
+/// Elsewhere we fall back to recognising common virtual interface names
+fn is_virtual(interface: &str) -> bool {
+    if is_loopback(interface) {
+        return true;
+    }
+    let sys_class_net = Path::new(SYS_CLASS_NET);
+    if sys_class_net.join(interface).exists() {
+        return !sys_class_net.join(interface).join("device").exists();
+    }
+    VIRTUAL_PREFIXES.iter().any(|prefix| interface.starts_with(prefix))
+}
@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::Path;
+use schema::{Pressure, PressureStall, PressureStalls};
+
+const PROC_PRESSURE: &str = "/proc/pressure";
+
+/// Reads the Pressure Stall Information of the CPU, memory and I/O. Kernels before 4.20, those built
+/// without PSI and those booted with psi=0 give None
+pub fn get_pressure() -> Option<Pressure> {
+    Some(Pressure {
+        cpu: read_stalls("cpu")?,
+        memory: read_stalls("memory")?,
+        io: read_stalls("io")?,
+    })
+}
+
+fn read_stalls(resource: &str) -> Option<PressureStalls> {
+    parse_stalls(&fs::read_to_string(Path::new(PROC_PRESSURE).join(resource)).ok()?)
+}
+
+/// Parses lines such as `some avg10=0.30 avg60=0.27 avg300=0.18 total=16079777`. The full line is
+/// missing for the CPU on kernels before 5.13
+fn parse_stalls(contents: &str) -> Option<PressureStalls> {
+    let (mut some, mut full) = (None, None);
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next()?;
+        let mut stall = PressureStall::default();
+        for field in fields {
+            let (key, value) = field.split_once('=')?;
+            match key {
+                "avg10" => stall.avg10 = value.parse().ok()?,
+                "avg60" => stall.avg60 = value.parse().ok()?,
+                "avg300" => stall.avg300 = value.parse().ok()?,
+                "total" => stall.total = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        match kind {
+            "some" => some = Some(stall),
+            "full" => full = Some(stall),
+            _ => {}
+        }
+    }
+    Some(PressureStalls { some: some?, full })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_before_5_13() {
+        let stalls = parse_stalls("some avg10=1.52 avg60=0.87 avg300=0.31 total=90251862\n").unwrap();
+        assert_eq!(stalls.some, PressureStall { avg10: 1.52, avg60: 0.87, avg300: 0.31, total: 90_251_862 });
+        assert_eq!(stalls.full, None);
+    }
+
+    #[test]
+    fn some_and_full() {
+        let contents = "\
+some avg10=12.05 avg60=8.40 avg300=2.13 total=431207710
+full avg10=4.50 avg60=3.01 avg300=0.77 total=208344915
+";
+        assert_eq!(parse_stalls(contents), Some(PressureStalls {
+            some: PressureStall { avg10: 12.05, avg60: 8.40, avg300: 2.13, total: 431_207_710 },
+            full: Some(PressureStall { avg10: 4.50, avg60: 3.01, avg300: 0.77, total: 208_344_915 }),
+        }));
+    }
+
+    #[test]
+    fn hosts_without_psi() {
+        // Reading /proc/pressure fails without PSI, but an empty or garbled file mustn't give zeros either
+        assert_eq!(parse_stalls(""), None);
+        assert_eq!(parse_stalls("full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n"), None);
+        assert_eq!(parse_stalls("some avg10=high avg60=0.00 avg300=0.00 total=0\n"), None);
+        assert_eq!(parse_stalls("some avg10 avg60 avg300 total\n"), None);
+        assert_eq!(parse_stalls("<not supported>\n"), None);
+    }
+}
@@ -0,0 +1,85 @@
+use serde::Deserialize;
+use sysinfo::{Pid, PidExt, Process, ProcessExt, System, SystemExt, UserExt};
+use schema::ProcessSort;
+
+/// Query parameters accepted by the /processes route, e.g. /processes?sort=cpu&limit=10
+#[derive(Deserialize, Debug, Default)]
+pub struct ProcessQuery {
+    pub sort: Option<ProcessSort>,
+    pub limit: Option<usize>,
+}
+
+impl ProcessQuery {
+    /// Sorts the processes as requested, by pid when no order is given, and keeps the first `limit` of them
+    fn apply(&self, processes: &mut Vec<schema::Process>) {
+        self.sort.unwrap_or(ProcessSort::Pid).sort(processes);
+        if let Some(limit) = self.limit {
+            processes.truncate(limit);
+        }
+    }
+}
+
+/// Collects every process known to the System, sorts them if requested and truncates the list
+/// to the given limit. Unsorted lists are ordered by pid
+pub fn get_processes(sys: &System, query: &ProcessQuery) -> Vec<schema::Process> {
+    let mut processes: Vec<schema::Process> = sys.processes()
+        .iter()
+        .map(|(pid, process)| process_info(sys, *pid, process))
+        .collect();
+    query.apply(&mut processes);
+    processes
+}
+
+fn process_info(sys: &System, pid: Pid, process: &Process) -> schema::Process {
+    let user = process.user_id()
+        .and_then(|uid| sys.get_user_by_id(uid))
+        .map(|user| user.name().to_string())
+        .unwrap_or_default();
+
+    schema::Process {
+        pid: pid.as_u32(),
+        name: process.name().to_string(),
+        command: process.cmd().join(" "),
+        user,
+        cpu_usage: process.cpu_usage(),
+        memory: process.memory(),
+        virtual_memory: process.virtual_memory(),
+        status: process.status().to_string(),
+        start_time: process.start_time(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, name: &str, cpu_usage: f32, memory: u64) -> schema::Process {
+        schema::Process { pid, name: name.to_string(), cpu_usage, memory, ..Default::default() }
+    }
+
+    /// The pids of the processes left after applying the query
+    fn pids(sort: Option<ProcessSort>, limit: Option<usize>) -> Vec<u32> {
+        let mut processes = vec![process(30, "sshd", 0.5, 8 << 20), process(4, "Xorg", 12.0, 200 << 20),
+                                 process(1200, "firefox", 35.0, 1 << 30), process(1, "systemd", 0.0, 12 << 20)];
+        ProcessQuery { sort, limit }.apply(&mut processes);
+        processes.iter().map(|process| process.pid).collect()
+    }
+
+    #[test]
+    fn each_sort_order() {
+        assert_eq!(pids(Some(ProcessSort::Cpu), None), [1200, 4, 30, 1]);
+        assert_eq!(pids(Some(ProcessSort::Memory), None), [1200, 4, 1, 30]);
+        assert_eq!(pids(Some(ProcessSort::Pid), None), [1, 4, 30, 1200]);
+        // Names are compared without regard to case
+        assert_eq!(pids(Some(ProcessSort::Name), None), [1200, 30, 1, 4]);
+        assert_eq!(pids(None, None), [1, 4, 30, 1200]);
+    }
+
+    #[test]
+    fn limit_keeps_the_top() {
+        assert_eq!(pids(Some(ProcessSort::Cpu), Some(2)), [1200, 4]);
+        assert_eq!(pids(None, Some(1)), [1]);
+        assert_eq!(pids(Some(ProcessSort::Memory), Some(10)), [1200, 4, 1, 30]);
+        assert!(pids(Some(ProcessSort::Cpu), Some(0)).is_empty());
+    }
+}
@@ -1,43 +1,82 @@
-use sysinfo::{CpuExt, DiskExt, NetworkExt, RefreshKind, System, SystemExt};
-use serde::{Serialize};
+use std::time::{Duration, Instant};
+use sysinfo::{CpuExt, CpuRefreshKind, Disk, DiskExt, NetworkExt, ProcessRefreshKind, RefreshKind, System, SystemExt};
+use schema::{Process, UsageScope, SCHEMA_VERSION};
+use crate::config::{Collectors, Config, Filter};
+use crate::processes::{get_processes, ProcessQuery};
+use crate::sensors::get_sensors;
+use crate::networks::get_network_interfaces;
+use crate::diskstats::get_disk_io;
+use crate::filesystems::{inodes, is_pseudo};
+use crate::meminfo::get_memory_breakdown;
+use crate::pressure::get_pressure;
+use crate::cgroups::CgroupReader;
+use crate::container::ContainerLimits;
 
-#[derive(Serialize, Debug)]
+/// Collects system information and keeps the latest snapshot of it in the shared wire format
+#[derive(Debug)]
 pub struct Resources {
-    hostname: String,
-    total_memory: u64,
-    used_memory: u64,
-    total_space: u64,
-    available_space: u64,
-    used_swap: u64,
-    total_swap: u64,
-    cpu_amount: usize,
-    cpu_usage: f32,
-    cpu_load_per_core: Vec<f32>,
-    disk_names: Vec<String>,
-    disk_available: Vec<u64>,
-    disk_total: Vec<u64>,
-    uptime: u64,
-    os_version: String,
-    kernel_version: String,
-    load_avg_one: f64,
-    load_avg_five: f64,
-    load_avg_fifteen: f64,
-    bytes_received: u64,
-    bytes_transmitted: u64,
-
-    #[serde(skip_serializing)]
+    snapshot: schema::Resources,
     system_struct: System,
+    /// The processes are kept apart, as refreshing them also refreshes the CPU times their usage is
+    /// measured against. Sharing those with the CPU collector would skew both when their intervals differ
+    process_system: System,
+    disk_filter: Filter,
+    file_system_filter: Filter,
+    network_filter: Filter,
+    cgroup_reader: CgroupReader,
+    /// The limits of the cgroup the agent runs in, if it has any
+    container: Option<ContainerLimits>,
+    usage_scope: UsageScope,
+    /// The enabled collectors, each with its interval and when it is next refreshed
+    schedule: Vec<Scheduled>,
+    /// A sample is published every update_frequency, however often the collectors are refreshed
+    publish_interval: Duration,
+    next_publish: Instant,
+}
+
+/// A part of the system information that is refreshed on an interval of its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Collector {
+    Cpu,
+    Memory,
+    Disks,
+    DiskIo,
+    Network,
+    Processes,
+    Sensors,
+    Pressure,
+    Cgroups,
+}
+
+#[derive(Debug)]
+struct Scheduled {
+    collector: Collector,
+    interval: Duration,
+    due: Instant,
 }
 
 impl Resources {
     /// Creates an instance of System and returns a Resources struct with desired system information
-    pub fn new() -> Self {
-        let mut sys = get_system();
-        let disk_space = disk_total_usage(&mut sys);
+    pub fn new(config: &Config) -> Self {
+        let collectors = config.collectors;
+        let sys = get_system(collectors);
+        let disk_filter = config.disks.clone();
+        let file_system_filter = config.file_systems.clone();
+        let network_filter = config.network.clone();
+        let mut cgroup_reader = CgroupReader::new(&config.cgroups);
+        let mut container = ContainerLimits::detect(&config.cgroups.root, sys.total_memory(), sys.cpus().len());
+        let usage_scope = match container {
+            Some(_) if config.container.relative_to_limits => UsageScope::Limits,
+            _ => UsageScope::Host,
+        };
+        let disks = Disks { names: &disk_filter, file_systems: &file_system_filter };
+        let disk_space = disk_total_usage(&sys, disks);
+        let (disk_inodes_total, disk_inodes_used) = get_disk_inodes(&sys, disks);
         let os_version = sys.long_os_version().unwrap_or_else(|| String::from("Unknown"));
         let kernel_version = sys.kernel_version().unwrap_or_else(|| String::from("Unknown"));
 
-        Resources {
+        let mut snapshot = schema::Resources {
+            schema_version: SCHEMA_VERSION,
             hostname: sys.host_name().unwrap(),
             total_memory: sys.total_memory(),
             used_memory: sys.used_memory(),
@@ -48,65 +87,238 @@ impl Resources {
             cpu_amount: sys.cpus().len(),
             cpu_usage: sys.global_cpu_info().cpu_usage(),
             cpu_load_per_core: get_cpu_load_per_core(&sys),
-            disk_names: get_disk_names(&mut sys),
-            disk_available: get_disks_available(&mut sys),
-            disk_total: get_disks_total(&mut sys),
+            disk_names: get_disk_names(&sys, disks),
+            disk_mount_points: get_disk_mount_points(&sys, disks),
+            disk_available: get_disks_available(&sys, disks),
+            disk_total: get_disks_total(&sys, disks),
+            disk_file_systems: get_disk_file_systems(&sys, disks),
+            disk_removable: get_disks_removable(&sys, disks),
+            disk_inodes_total,
+            disk_inodes_used,
             uptime: sys.uptime(),
             os_version,
             kernel_version,
             load_avg_one: sys.load_average().one,
             load_avg_five: sys.load_average().five,
             load_avg_fifteen: sys.load_average().fifteen,
-            bytes_received: get_total_received(&sys),
-            bytes_transmitted: get_total_transmitted(&sys),
+            bytes_received: get_total_received(&sys, &network_filter),
+            bytes_transmitted: get_total_transmitted(&sys, &network_filter),
+            networks: get_network_interfaces(&sys, &network_filter),
+            sensors: get_sensors(sys.components()),
+            disk_io: if collectors.disks { get_disk_io(&disk_filter) } else { vec![] },
+            memory: get_memory_breakdown(),
+            pressure: if collectors.pressure { get_pressure() } else { None },
+            cgroups: if collectors.cgroups { cgroup_reader.read() } else { vec![] },
+            limits: container.as_ref().map(|container| container.limits.clone()),
+            usage_scope,
+        };
+        if let (Some(container), UsageScope::Limits) = (container.as_mut(), usage_scope) {
+            limit_cpu_usage(container, &mut snapshot);
+            limit_memory(container, &mut snapshot);
+        }
+
+        // Publishing starts together with the collectors, so those on update_frequency are refreshed in time for it
+        let now = Instant::now();
+        let publish_interval = Duration::from_millis(config.update_frequency);
+        let next_publish = now + publish_interval;
+        Resources {
+            snapshot,
             system_struct: sys,
+            process_system: get_process_system(collectors.processes),
+            disk_filter,
+            file_system_filter,
+            network_filter,
+            cgroup_reader,
+            container,
+            usage_scope,
+            schedule: schedule(config, now),
+            publish_interval,
+            next_publish,
+        }
+    }
+
+    /// When the next collector is due to be refreshed or the next sample to be published
+    pub fn next_refresh(&self) -> Instant {
+        self.schedule.iter().map(|scheduled| scheduled.due).fold(self.next_publish, Instant::min)
+    }
+
+    /// Refreshes the collectors that are due and returns whether a sample of the resources is due to be
+    /// published. The hostname, OS and kernel versions are only read once, in new
+    pub fn refresh(&mut self, now: Instant) -> bool {
+        for i in 0..self.schedule.len() {
+            let scheduled = &mut self.schedule[i];
+            if scheduled.due > now {
+                continue;
+            }
+            // Refreshes that fell behind are skipped rather than caught up on
+            while scheduled.due <= now {
+                scheduled.due += scheduled.interval;
+            }
+            let collector = scheduled.collector;
+            self.refresh_collector(collector);
+        }
+        self.snapshot.uptime = self.system_struct.uptime();
 
+        if self.next_publish > now {
+            return false;
+        }
+        while self.next_publish <= now {
+            self.next_publish += self.publish_interval;
         }
+        true
     }
 
-    /// Refreshes the desired fields of our Resources struct
-    pub(crate) fn refresh(&mut self) {
-        self.system_struct.refresh_all();
-        self.cpu_usage = self.system_struct.global_cpu_info().cpu_usage();
-        self.used_memory = self.system_struct.used_memory();
-        self.available_space = disk_total_usage(&mut self.system_struct).1;
-        self.used_swap = self.system_struct.used_swap();
-        self.cpu_load_per_core = get_cpu_load_per_core(&self.system_struct);
-        self.disk_available = get_disks_available(&mut self.system_struct);
-        self.uptime = self.system_struct.uptime();
-        self.load_avg_one = self.system_struct.load_average().one;
-        self.load_avg_five = self.system_struct.load_average().five;
-        self.load_avg_fifteen = self.system_struct.load_average().fifteen;
-        self.bytes_transmitted = get_total_transmitted(&self.system_struct);
-        self.bytes_received = get_total_received(&self.system_struct);
+    fn refresh_collector(&mut self, collector: Collector) {
+        let sys = &mut self.system_struct;
+        let snapshot = &mut self.snapshot;
+        match collector {
+            Collector::Cpu => {
+                sys.refresh_cpu_specifics(CpuRefreshKind::new().with_cpu_usage());
+                snapshot.cpu_usage = sys.global_cpu_info().cpu_usage();
+                snapshot.cpu_load_per_core = get_cpu_load_per_core(sys);
+                let load_average = sys.load_average();
+                snapshot.load_avg_one = load_average.one;
+                snapshot.load_avg_five = load_average.five;
+                snapshot.load_avg_fifteen = load_average.fifteen;
+                if let (Some(container), UsageScope::Limits) = (self.container.as_mut(), self.usage_scope) {
+                    limit_cpu_usage(container, snapshot);
+                }
+            }
+            Collector::Memory => {
+                sys.refresh_memory();
+                snapshot.used_memory = sys.used_memory();
+                snapshot.used_swap = sys.used_swap();
+                snapshot.total_swap = sys.total_swap();
+                snapshot.memory = get_memory_breakdown();
+                if let (Some(container), UsageScope::Limits) = (self.container.as_ref(), self.usage_scope) {
+                    limit_memory(container, snapshot);
+                }
+            }
+            Collector::Disks => {
+                sys.refresh_disks_list();
+                let disks = Disks { names: &self.disk_filter, file_systems: &self.file_system_filter };
+                let (total_space, available_space) = disk_total_usage(sys, disks);
+                snapshot.total_space = total_space;
+                snapshot.available_space = available_space;
+                snapshot.disk_names = get_disk_names(sys, disks);
+                snapshot.disk_mount_points = get_disk_mount_points(sys, disks);
+                snapshot.disk_available = get_disks_available(sys, disks);
+                snapshot.disk_total = get_disks_total(sys, disks);
+                snapshot.disk_file_systems = get_disk_file_systems(sys, disks);
+                snapshot.disk_removable = get_disks_removable(sys, disks);
+                (snapshot.disk_inodes_total, snapshot.disk_inodes_used) = get_disk_inodes(sys, disks);
+            }
+            Collector::DiskIo => snapshot.disk_io = get_disk_io(&self.disk_filter),
+            Collector::Network => {
+                sys.refresh_networks();
+                snapshot.bytes_transmitted = get_total_transmitted(sys, &self.network_filter);
+                snapshot.bytes_received = get_total_received(sys, &self.network_filter);
+                snapshot.networks = get_network_interfaces(sys, &self.network_filter);
+            }
+            Collector::Processes => self.process_system.refresh_processes_specifics(ProcessRefreshKind::everything()),
+            Collector::Sensors => {
+                sys.refresh_components();
+                snapshot.sensors = get_sensors(sys.components());
+            }
+            Collector::Pressure => snapshot.pressure = get_pressure(),
+            Collector::Cgroups => snapshot.cgroups = self.cgroup_reader.read(),
+        }
+    }
+
+    /// The resources as of the last refresh
+    pub fn snapshot(&self) -> &schema::Resources {
+        &self.snapshot
     }
 
     pub fn serialize(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(self)
+        serde_json::to_string(&self.snapshot)
+    }
+
+    /// Returns the processes currently running, sorted and limited according to the query
+    pub fn processes(&self, query: &ProcessQuery) -> Vec<Process> {
+        get_processes(&self.process_system, query)
+    }
+}
+
+/// Replaces the CPU usage of the host with that of the agent's cgroup against its limit, if it has one.
+/// The load per core is left at the host's, cgroup v2 doesn't account the CPU time per core
+fn limit_cpu_usage(container: &mut ContainerLimits, snapshot: &mut schema::Resources) {
+    if let Some(usage) = container.cpu_usage() {
+        snapshot.cpu_usage = usage;
+    }
+}
+
+/// Replaces the memory of the host with the limit of the agent's cgroup and what it uses, if it has a limit
+fn limit_memory(container: &ContainerLimits, snapshot: &mut schema::Resources) {
+    if let (Some(limit), Some(used)) = (container.limits.memory, container.memory_used()) {
+        snapshot.total_memory = limit;
+        snapshot.used_memory = used;
     }
 }
 
-/// Iterates through each disk summing up the total and available space on the system
-fn disk_total_usage(sys: &mut System) -> (u64, u64) {
-    let (total, available) = sys.disks().iter().fold((0, 0), |acc, disk| {
-        (acc.0 + disk.total_space(), acc.1 + disk.available_space())
-    });
+/// The filters a disk has to pass to be reported
+#[derive(Clone, Copy)]
+struct Disks<'a> {
+    /// Matched against the name of the disk and where it is mounted
+    names: &'a Filter,
+    /// Matched against the filesystem type
+    file_systems: &'a Filter,
+}
+
+/// The disks let through by the filters
+fn filtered_disks<'a>(sys: &'a System, filters: Disks<'a>) -> impl Iterator<Item = &'a Disk> {
+    sys.disks().iter().filter(move |disk| {
+        filters.names.allows(&[&disk.name().to_string_lossy(), &disk.mount_point().to_string_lossy()])
+            && filters.file_systems.allows(&[&file_system(disk)])
+    })
+}
+
+fn file_system(disk: &Disk) -> String {
+    String::from_utf8_lossy(disk.file_system()).to_string()
+}
+
+/// Iterates through each disk summing up the total and available space on the system. Pseudo
+/// filesystems are left out, as their space is in memory or already counted on another disk
+fn disk_total_usage(sys: &System, filters: Disks) -> (u64, u64) {
+    let (total, available) = filtered_disks(sys, filters)
+        .filter(|disk| !is_pseudo(&file_system(disk)))
+        .fold((0, 0), |acc, disk| (acc.0 + disk.total_space(), acc.1 + disk.available_space()));
     (total, available)
 }
 
 /// Iterates through each disk and returning the disk names
-fn get_disk_names(sys: &mut System) -> Vec<String> {
-    sys.disks().iter().map(|disk| disk.name().to_string_lossy().to_string()).collect()
+fn get_disk_names(sys: &System, filters: Disks) -> Vec<String> {
+    filtered_disks(sys, filters).map(|disk| disk.name().to_string_lossy().to_string()).collect()
+}
+
+/// Iterates through each disk returning where it is mounted
+fn get_disk_mount_points(sys: &System, filters: Disks) -> Vec<String> {
+    filtered_disks(sys, filters).map(|disk| disk.mount_point().to_string_lossy().to_string()).collect()
 }
 
 /// Iterates through each disk returning the space available
-fn get_disks_available(sys: &mut System) -> Vec<u64> {
-    sys.disks().iter().map(|disk| disk.available_space()).collect()
+fn get_disks_available(sys: &System, filters: Disks) -> Vec<u64> {
+    filtered_disks(sys, filters).map(|disk| disk.available_space()).collect()
 }
 
 /// Iterates through each disk returning the total capacity
-fn get_disks_total(sys: &mut System) -> Vec<u64> {
-    sys.disks().iter().map(|disk| disk.total_space()).collect()
+fn get_disks_total(sys: &System, filters: Disks) -> Vec<u64> {
+    filtered_disks(sys, filters).map(|disk| disk.total_space()).collect()
+}
+
+/// Iterates through each disk returning its filesystem type
+fn get_disk_file_systems(sys: &System, filters: Disks) -> Vec<String> {
+    filtered_disks(sys, filters).map(file_system).collect()
+}
+
+/// Iterates through each disk returning whether it can be removed, such as a USB stick
+fn get_disks_removable(sys: &System, filters: Disks) -> Vec<bool> {
+    filtered_disks(sys, filters).map(|disk| disk.is_removable()).collect()
+}
+
+/// Iterates through each disk returning its total and used inodes
+fn get_disk_inodes(sys: &System, filters: Disks) -> (Vec<u64>, Vec<u64>) {
+    filtered_disks(sys, filters).map(|disk| inodes(disk.mount_point())).unzip()
 }
 
 /// Iterates through each core returning the usage %
@@ -114,34 +326,139 @@ fn get_cpu_load_per_core(sys: &System) -> Vec<f32> {
     sys.cpus().iter().map(|cpu| cpu.cpu_usage()).collect()
 }
 
-///Iterates over interfaces and returns total bytes received
-fn get_total_received(sys: &System) -> u64 {
+///Iterates over the interfaces let through by the filter and returns total bytes received
+fn get_total_received(sys: &System, filter: &Filter) -> u64 {
     let networks = sys.networks();
     let mut total = 0;
-    for (_, network) in networks {
-        total += network.total_received();
+    for (name, network) in networks {
+        if filter.allows(&[name]) {
+            total += network.total_received();
+        }
     }
     total
 }
 
-///Iterates over interfaces and returns total bytes transmitted
-fn get_total_transmitted(sys: &System) -> u64 {
+///Iterates over the interfaces let through by the filter and returns total bytes transmitted
+fn get_total_transmitted(sys: &System, filter: &Filter) -> u64 {
     let networks = sys.networks();
     let mut total = 0;
-    for (_, network) in networks {
-        total += network.total_transmitted();
+    for (name, network) in networks {
+        if filter.allows(&[name]) {
+            total += network.total_transmitted();
+        }
     }
     total
 }
 
-/// Returns a System struct using System::new_with_specifics and calls refresh.all() before returning
-fn get_system() -> System {
-    let mut sys = System::new_with_specifics(
-        RefreshKind::everything()
-            .without_components()
-            .without_processes()
-            .without_users_list()
-    );
-    sys.refresh_all();
+/// The enabled collectors with their intervals, all first due one interval from now as new refreshed everything.
+/// Starting together keeps collectors whose intervals are multiples of each other refreshed at the same time
+fn schedule(config: &Config, now: Instant) -> Vec<Scheduled> {
+    let collectors = config.collectors;
+    let intervals = config.intervals;
+    let candidates = [
+        (Collector::Cpu, true, intervals.cpu),
+        (Collector::Memory, true, intervals.memory),
+        (Collector::Disks, collectors.disks, intervals.disks),
+        (Collector::DiskIo, collectors.disks, intervals.disk_io),
+        (Collector::Network, collectors.network, intervals.network),
+        (Collector::Processes, collectors.processes, intervals.processes),
+        (Collector::Sensors, collectors.sensors, intervals.sensors),
+        (Collector::Pressure, collectors.pressure, intervals.pressure),
+        (Collector::Cgroups, collectors.cgroups, intervals.cgroups),
+    ];
+    candidates.into_iter()
+        .filter(|(_, enabled, _)| *enabled)
+        .map(|(collector, _, interval)| {
+            let interval = Duration::from_millis(interval.unwrap_or(config.update_frequency));
+            Scheduled { collector, interval, due: now + interval }
+        })
+        .collect()
+}
+
+/// What is loaded when the agent starts, only the enabled collectors besides the CPU and memory.
+/// The processes have a System of their own
+fn refresh_kind(collectors: Collectors) -> RefreshKind {
+    let mut kind = RefreshKind::new()
+        .with_cpu(CpuRefreshKind::everything())
+        .with_memory();
+    if collectors.disks {
+        kind = kind.with_disks();
+    }
+    if collectors.network {
+        kind = kind.with_networks();
+    }
+    if collectors.sensors {
+        kind = kind.with_components();
+    }
+    kind
+}
+
+/// Returns a System struct with the lists of the enabled collectors loaded and refreshed once
+fn get_system(collectors: Collectors) -> System {
+    let mut kind = refresh_kind(collectors);
+    if collectors.disks {
+        kind = kind.with_disks_list();
+    }
+    if collectors.network {
+        kind = kind.with_networks_list();
+    }
+    if collectors.sensors {
+        kind = kind.with_components_list();
+    }
+    let mut sys = System::new_with_specifics(kind);
+    sys.refresh_specifics(kind);
+    sys
+}
+
+/// Returns a System with the processes and the users they belong to loaded, or an empty one
+/// when the collector is disabled
+fn get_process_system(enabled: bool) -> System {
+    if !enabled {
+        return System::new();
+    }
+    let kind = RefreshKind::new()
+        .with_processes(ProcessRefreshKind::everything())
+        .with_users_list();
+    let mut sys = System::new_with_specifics(kind);
+    sys.refresh_specifics(kind);
     sys
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Follows the schedule for the duration and returns when samples were published, relative to the start
+    fn published(resources: &mut Resources, start: Instant, duration: Duration) -> Vec<Duration> {
+        let mut published = vec![];
+        loop {
+            let now = resources.next_refresh();
+            if now - start > duration {
+                return published;
+            }
+            if resources.refresh(now) {
+                published.push(now - start);
+            }
+        }
+    }
+
+    #[test]
+    fn samples_are_published_every_update_frequency() {
+        let mut config = Config { update_frequency: 1000, ..Default::default() };
+        config.intervals.cpu = Some(250);
+        config.intervals.network = Some(3000);
+        config.collectors.processes = false;
+        let mut resources = Resources::new(&config);
+        // The CPU is refreshed first, one interval after the schedule started
+        let start = resources.next_refresh() - Duration::from_millis(250);
+
+        let seconds = |seconds: &[u64]| seconds.iter().map(|s| Duration::from_secs(*s)).collect::<Vec<_>>();
+        assert_eq!(published(&mut resources, start, Duration::from_millis(4500)), seconds(&[1, 2, 3, 4]));
+
+        // Samples that fell behind are skipped rather than caught up on
+        assert!(resources.refresh(start + Duration::from_millis(7500)));
+        assert!(!resources.refresh(start + Duration::from_millis(7900)));
+        assert_eq!(resources.next_refresh(), start + Duration::from_secs(8));
+        assert!(resources.refresh(start + Duration::from_secs(8)));
+    }
+}
@@ -0,0 +1,88 @@
+use schema::Sensor;
+use sysinfo::ComponentExt;
+
+/// Reads the label and temperatures of each hardware component. Generic over the component
+/// source so it isn't tied to the sensors of the machine we happen to run on.
+/// Components that can't report a temperature are skipped, so a machine without
+/// sensors simply gives an empty list
+pub fn get_sensors<'a, C, I>(components: I) -> Vec<Sensor>
+    where C: ComponentExt + 'a,
+          I: IntoIterator<Item = &'a C>
+{
+    components.into_iter()
+        .filter(|component| component.temperature().is_finite())
+        .map(|component| Sensor {
+            label: component.label().to_string(),
+            temperature: component.temperature(),
+            max: Some(component.max()).filter(|max| max.is_finite()),
+            critical: component.critical().filter(|critical| critical.is_finite()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A component with fixed readings, in place of the sensors of the machine running the tests
+    #[derive(Debug)]
+    struct FakeComponent {
+        label: &'static str,
+        temperature: f32,
+        max: f32,
+        critical: Option<f32>,
+    }
+
+    impl ComponentExt for FakeComponent {
+        fn temperature(&self) -> f32 {
+            self.temperature
+        }
+
+        fn max(&self) -> f32 {
+            self.max
+        }
+
+        fn critical(&self) -> Option<f32> {
+            self.critical
+        }
+
+        fn label(&self) -> &str {
+            self.label
+        }
+
+        fn refresh(&mut self) {}
+    }
+
+    #[test]
+    fn no_components_give_no_sensors() {
+        assert!(get_sensors::<FakeComponent, _>(&[]).is_empty());
+    }
+
+    #[test]
+    fn components_without_a_temperature_are_skipped() {
+        let components = [
+            FakeComponent { label: "acpitz", temperature: f32::NAN, max: 90.0, critical: Some(100.0) },
+            FakeComponent { label: "nvme Composite", temperature: 41.5, max: 60.0, critical: Some(84.8) },
+        ];
+        assert_eq!(get_sensors(&components), [Sensor {
+            label: "nvme Composite".to_string(),
+            temperature: 41.5,
+            max: Some(60.0),
+            critical: Some(84.8),
+        }]);
+    }
+
+    #[test]
+    fn unknown_thresholds_become_none() {
+        let components = [
+            FakeComponent { label: "coretemp Package id 0", temperature: 55.0, max: f32::NAN, critical: Some(f32::NAN) },
+            FakeComponent { label: "k10temp Tctl", temperature: 48.0, max: f32::INFINITY, critical: None },
+        ];
+        let sensors = get_sensors(&components);
+        assert_eq!(sensors.len(), 2);
+        for sensor in sensors {
+            assert_eq!(sensor.max, None, "{}", sensor.label);
+            assert_eq!(sensor.critical, None, "{}", sensor.label);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls_pemfile::Item;
+use crate::config::Tls;
+
+/// The PEM files the HTTPS configuration is built from, kept so it can be rebuilt on SIGHUP
+pub struct TlsFiles {
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<PathBuf>,
+}
+
+impl TlsFiles {
+    /// None when no certificate was given and the agent serves plain HTTP
+    pub fn from_config(tls: &Tls) -> Option<TlsFiles> {
+        match (&tls.cert, &tls.key) {
+            (Some(cert), Some(key)) => Some(TlsFiles {
+                cert: cert.clone(),
+                key: key.clone(),
+                client_ca: tls.client_ca.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Reads the certificate chain, the key and, for mTLS, the client CAs
+    pub fn server_config(&self) -> Result<ServerConfig, String> {
+        let certs = read_certs(&self.cert)?;
+        let key = read_key(&self.key)?;
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(client_ca)? {
+                    roots.add(&cert)
+                        .map_err(|err| format!("Invalid CA certificate in {}: {}", client_ca.display(), err))?;
+                }
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_single_cert(certs, key)
+            .map_err(|err| format!("Invalid certificate or key: {}", err))?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+
+    /// Rebuilds the configuration every time the agent gets a SIGHUP, so renewed certificates are
+    /// picked up without a restart. A configuration that fails to load is logged and the old one kept
+    #[cfg(unix)]
+    pub async fn reload_on_hangup(self, config: RustlsConfig) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(err) => {
+                eprintln!("Failed to listen for SIGHUP, certificates won't be reloaded: {}", err);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            match self.server_config() {
+                Ok(server_config) => {
+                    config.reload_from_config(Arc::new(server_config));
+                    eprintln!("Reloaded the TLS certificate");
+                }
+                Err(err) => eprintln!("Failed to reload the TLS certificate, keeping the old one: {}", err),
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub async fn reload_on_hangup(self, _config: RustlsConfig) {}
+}
+
+fn read_certs(path: &Path) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|err| format!("Failed to read certificates from {}: {}", path.display(), err))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path.display()));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Takes the first PKCS#8, PKCS#1 or SEC1 private key in the file
+fn read_key(path: &Path) -> Result<PrivateKey, String> {
+    let file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|err| format!("Failed to read the key from {}: {}", path.display(), err))?;
+    items.into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key found in {}", path.display()))
+}
@@ -0,0 +1,49 @@
+//! What the config files of the agent and the dashboard have in common
+
+use std::ops::Range;
+use std::path::Path;
+
+/// Points at the line of a parse error and names the key on it, e.g.
+/// `dashboard.toml:7: timeout: invalid type: string "fast", expected u64`.
+/// Takes the message and span of the TOML error, so this crate doesn't depend on the parser
+pub fn describe_error(path: &Path, contents: &str, message: &str, span: Option<Range<usize>>) -> String {
+    let message = message.trim().replace('\n', ", ");
+    let span = match span {
+        Some(span) => span,
+        None => return format!("{}: {}", path.display(), message),
+    };
+    let line_number = contents[..span.start].matches('\n').count() + 1;
+    let line = contents.lines().nth(line_number - 1).unwrap_or_default();
+    match line.split_once('=') {
+        Some((key, _)) => format!("{}:{}: {}: {}", path.display(), line_number, key.trim(), message),
+        None => format!("{}:{}: {}", path.display(), line_number, message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &str = "theme = \"dark\"\n\n[[server]]\ntimeout = \"fast\"\n";
+
+    #[test]
+    fn names_the_line_and_key() {
+        let start = CONTENTS.find("\"fast\"").unwrap();
+        let description = describe_error(Path::new("dashboard.toml"), CONTENTS,
+                                         "invalid type: string \"fast\", expected u64\n", Some(start..start + 6));
+        assert_eq!(description, "dashboard.toml:4: timeout: invalid type: string \"fast\", expected u64");
+    }
+
+    #[test]
+    fn lines_without_a_key() {
+        let start = CONTENTS.find("[[server]]").unwrap();
+        let description = describe_error(Path::new("dashboard.toml"), CONTENTS, "missing field `url`", Some(start..start + 10));
+        assert_eq!(description, "dashboard.toml:3: missing field `url`");
+    }
+
+    #[test]
+    fn errors_without_a_span() {
+        let description = describe_error(Path::new("agent.toml"), CONTENTS, "unexpected end\nof input", None);
+        assert_eq!(description, "agent.toml: unexpected end, of input");
+    }
+}
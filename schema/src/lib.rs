@@ -0,0 +1,438 @@
+//! The wire format shared by the agent and the dashboard. The agent serializes these types
+//! and the dashboard deserializes them, so the two can't drift apart.
+
+pub mod config;
+
+use std::cmp::Reverse;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever a field is removed or changes meaning. Fields added later are marked
+/// with #[serde(default)] so payloads from older agents still deserialize
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The payload of the agent's /resources route
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Resources {
+    /// Agents from before the schema was versioned don't send this and are read as version 0
+    #[serde(default)]
+    pub schema_version: u32,
+    pub hostname: String,
+    pub total_memory: u64,
+    pub used_memory: u64,
+    pub total_space: u64,
+    pub available_space: u64,
+    pub used_swap: u64,
+    pub total_swap: u64,
+    pub cpu_amount: usize,
+    pub cpu_usage: f32,
+    /// Always the host's cores, also when cpu_usage is measured against a CPU limit
+    pub cpu_load_per_core: Vec<f32>,
+    pub disk_names: Vec<String>,
+    #[serde(default)]
+    pub disk_mount_points: Vec<String>,
+    pub disk_available: Vec<u64>,
+    pub disk_total: Vec<u64>,
+    /// The filesystem type of each disk, e.g. ext4
+    #[serde(default)]
+    pub disk_file_systems: Vec<String>,
+    #[serde(default)]
+    pub disk_removable: Vec<bool>,
+    /// Zero for filesystems that allocate inodes as they go
+    #[serde(default)]
+    pub disk_inodes_total: Vec<u64>,
+    #[serde(default)]
+    pub disk_inodes_used: Vec<u64>,
+    pub uptime: u64,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub load_avg_one: f64,
+    pub load_avg_five: f64,
+    pub load_avg_fifteen: f64,
+    pub bytes_received: u64,
+    pub bytes_transmitted: u64,
+    #[serde(default)]
+    pub networks: Vec<NetworkInterface>,
+    #[serde(default)]
+    pub sensors: Vec<Sensor>,
+    #[serde(default)]
+    pub disk_io: Vec<DiskIo>,
+    /// Only reported on Linux
+    #[serde(default)]
+    pub memory: Option<MemoryBreakdown>,
+    /// Only reported on Linux 4.20 and later, with PSI enabled
+    #[serde(default)]
+    pub pressure: Option<Pressure>,
+    #[serde(default)]
+    pub cgroups: Vec<Cgroup>,
+    /// The limits of the cgroup the agent runs in, such as a container's, when it has any
+    #[serde(default)]
+    pub limits: Option<Limits>,
+    /// What cpu_usage, used_memory and total_memory are measured against. cpu_load_per_core stays
+    /// the load of the host's cores either way, as a CPU limit doesn't pin the cgroup to cores
+    #[serde(default)]
+    pub usage_scope: UsageScope,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub received: u64,
+    pub transmitted: u64,
+    pub packets_received: u64,
+    pub packets_transmitted: u64,
+    pub errors_received: u64,
+    pub errors_transmitted: u64,
+    pub drops_received: u64,
+    pub drops_transmitted: u64,
+    pub is_loopback: bool,
+    pub is_virtual: bool,
+}
+
+/// A hardware sensor, temperatures are in degrees Celsius
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Sensor {
+    pub label: String,
+    pub temperature: f32,
+    pub max: Option<f32>,
+    pub critical: Option<f32>,
+}
+
+/// The I/O a block device has done since boot, from /proc/diskstats. Times are in milliseconds
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct DiskIo {
+    pub name: String,
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    /// Completed read operations
+    pub reads: u64,
+    pub writes: u64,
+    pub read_time: u64,
+    pub write_time: u64,
+    /// Time the device was busy with at least one request, divided by the elapsed time it gives the utilisation
+    pub io_time: u64,
+}
+
+/// Where the memory goes, from /proc/meminfo, in bytes
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct MemoryBreakdown {
+    /// How much can be allocated without swapping, counting the cache that can be dropped
+    pub available: u64,
+    pub free: u64,
+    pub buffers: u64,
+    /// The page cache and the kernel caches that can be reclaimed
+    pub cached: u64,
+    /// tmpfs and shared memory, counted as part of the cache
+    pub shared: u64,
+    /// Waiting to be written back to disk
+    pub dirty: u64,
+    /// Swapped in and out since boot, from /proc/vmstat
+    pub swapped_in: u64,
+    pub swapped_out: u64,
+}
+
+/// Pressure Stall Information from /proc/pressure, how much of the time tasks had to wait for each resource
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Pressure {
+    pub cpu: PressureStalls,
+    pub memory: PressureStalls,
+    pub io: PressureStalls,
+}
+
+/// Some is the time at least one task was stalled, full the time all tasks that weren't idle were
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct PressureStalls {
+    pub some: PressureStall,
+    /// Missing for the CPU on kernels before 5.13, and always zero for it on the host as a whole
+    pub full: Option<PressureStall>,
+}
+
+/// The share of the time stalled in percent over the last 10, 60 and 300 seconds, and the total in microseconds
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct PressureStall {
+    pub avg10: f32,
+    pub avg60: f32,
+    pub avg300: f32,
+    pub total: u64,
+}
+
+/// A cgroup of the cgroup v2 hierarchy. The fields of controllers that aren't enabled for it are zero
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Cgroup {
+    /// Relative to the root of the hierarchy, e.g. /system.slice/nginx.service
+    pub path: String,
+    /// In percent of a single core, over the time since the previous refresh
+    pub cpu_usage: f32,
+    /// The CPU time used so far, in microseconds
+    pub cpu_time: u64,
+    pub memory_current: u64,
+    /// None when the memory isn't limited
+    pub memory_max: Option<u64>,
+    pub io_read_bytes: u64,
+    pub io_written_bytes: u64,
+    /// The number of processes and threads
+    pub pids: u64,
+}
+
+impl Resources {
+    /// Whether the agent was built against a newer schema than this crate, so fields may have
+    /// changed meaning and what is shown of it can't be trusted
+    pub fn from_newer_agent(&self) -> bool {
+        self.schema_version > SCHEMA_VERSION
+    }
+
+    /// Whether cpu_usage is measured against the CPU limit of the agent's cgroup rather than the host
+    pub fn cpu_limited(&self) -> bool {
+        self.usage_scope == UsageScope::Limits && self.limits.as_ref().is_some_and(|limits| limits.cpus.is_some())
+    }
+
+    /// Whether used_memory and total_memory are those of the agent's cgroup rather than the host
+    pub fn memory_limited(&self) -> bool {
+        self.usage_scope == UsageScope::Limits && self.limits.as_ref().is_some_and(|limits| limits.memory.is_some())
+    }
+}
+
+/// The CPU and memory limits of a cgroup, each only when it is below what the host has
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// In CPUs, e.g. 1.5 for a quota of 150 ms every 100 ms
+    pub cpus: Option<f64>,
+    pub memory: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageScope {
+    /// The whole host
+    #[default]
+    Host,
+    /// The limits of the agent's cgroup, for the resources that have one
+    Limits,
+}
+
+/// An entry of the agent's /processes route
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+    pub command: String,
+    pub user: String,
+    pub cpu_usage: f32,
+    pub memory: u64,
+    pub virtual_memory: u64,
+    pub status: String,
+    pub start_time: u64,
+}
+
+/// The sort parameter of the agent's /processes route
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessSort {
+    #[default]
+    Cpu,
+    #[serde(rename = "mem")]
+    Memory,
+    Pid,
+    Name,
+}
+
+impl ProcessSort {
+    pub const ALL: [ProcessSort; 4] = [ProcessSort::Cpu, ProcessSort::Memory, ProcessSort::Pid, ProcessSort::Name];
+
+    /// The value used in the query string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessSort::Cpu => "cpu",
+            ProcessSort::Memory => "mem",
+            ProcessSort::Pid => "pid",
+            ProcessSort::Name => "name",
+        }
+    }
+
+    /// CPU and memory are sorted in descending order, pid and name ascending
+    pub fn sort(&self, processes: &mut [Process]) {
+        match self {
+            ProcessSort::Cpu => processes.sort_by(|a, b| b.cpu_usage.total_cmp(&a.cpu_usage)),
+            ProcessSort::Memory => processes.sort_by_key(|process| Reverse(process.memory)),
+            ProcessSort::Pid => processes.sort_by_key(|process| process.pid),
+            ProcessSort::Name => processes.sort_by_key(|process| process.name.to_lowercase()),
+        }
+    }
+}
+
+impl fmt::Display for ProcessSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let title = match self {
+            ProcessSort::Cpu => "CPU",
+            ProcessSort::Memory => "Memory",
+            ProcessSort::Pid => "PID",
+            ProcessSort::Name => "Name",
+        };
+        write!(f, "{}", title)
+    }
+}
+
+/// An entry of the agent's /history route. Only the timestamp is always present, the other
+/// fields can be left out with the fields parameter and then default to zero
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct HistorySample {
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub cpu_usage: f32,
+    pub used_memory: u64,
+    pub total_memory: u64,
+    pub used_swap: u64,
+    pub total_swap: u64,
+    pub load_avg_one: f64,
+    pub bytes_received: u64,
+    pub bytes_transmitted: u64,
+    /// Summed over every block device
+    pub disk_read_bytes: u64,
+    pub disk_written_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stall(avg10: f32, total: u64) -> PressureStall {
+        PressureStall { avg10, avg60: avg10 / 2.0, avg300: avg10 / 4.0, total }
+    }
+
+    /// Every field set to something other than its default, so a field that is lost on the way shows up
+    fn filled_in() -> Resources {
+        Resources {
+            schema_version: SCHEMA_VERSION,
+            hostname: "web-1".to_string(),
+            total_memory: 16 << 30,
+            used_memory: 6 << 30,
+            total_space: 512 << 30,
+            available_space: 200 << 30,
+            used_swap: 1 << 30,
+            total_swap: 4 << 30,
+            cpu_amount: 4,
+            cpu_usage: 37.5,
+            cpu_load_per_core: vec![10.0, 20.0, 50.0, 70.0],
+            disk_names: vec!["nvme0n1p2".to_string(), "sdb1".to_string()],
+            disk_mount_points: vec!["/".to_string(), "/media/usb".to_string()],
+            disk_available: vec![200 << 30, 8 << 30],
+            disk_total: vec![480 << 30, 32 << 30],
+            disk_file_systems: vec!["ext4".to_string(), "vfat".to_string()],
+            disk_removable: vec![false, true],
+            disk_inodes_total: vec![31_457_280, 0],
+            disk_inodes_used: vec![1_200_000, 0],
+            uptime: 86_400,
+            os_version: "Linux 22.04 Ubuntu".to_string(),
+            kernel_version: "6.5.0".to_string(),
+            load_avg_one: 1.5,
+            load_avg_five: 1.25,
+            load_avg_fifteen: 0.75,
+            bytes_received: 123_456,
+            bytes_transmitted: 654_321,
+            networks: vec![NetworkInterface {
+                name: "eth0".to_string(),
+                received: 123_456,
+                transmitted: 654_321,
+                packets_received: 1_000,
+                packets_transmitted: 2_000,
+                errors_received: 1,
+                errors_transmitted: 2,
+                drops_received: 3,
+                drops_transmitted: 4,
+                is_loopback: false,
+                is_virtual: true,
+            }],
+            sensors: vec![Sensor { label: "nvme Composite".to_string(), temperature: 41.5, max: Some(60.0), critical: None }],
+            disk_io: vec![DiskIo {
+                name: "nvme0n1".to_string(),
+                read_bytes: 4096,
+                written_bytes: 8192,
+                reads: 1,
+                writes: 2,
+                read_time: 3,
+                write_time: 4,
+                io_time: 5,
+            }],
+            memory: Some(MemoryBreakdown {
+                available: 10 << 30,
+                free: 2 << 30,
+                buffers: 1 << 20,
+                cached: 7 << 30,
+                shared: 1 << 28,
+                dirty: 1 << 16,
+                swapped_in: 4096,
+                swapped_out: 8192,
+            }),
+            pressure: Some(Pressure {
+                cpu: PressureStalls { some: stall(2.0, 1_000), full: None },
+                memory: PressureStalls { some: stall(1.0, 500), full: Some(stall(0.5, 250)) },
+                io: PressureStalls { some: stall(8.0, 4_000), full: Some(stall(4.0, 2_000)) },
+            }),
+            cgroups: vec![Cgroup {
+                path: "/system.slice/nginx.service".to_string(),
+                cpu_usage: 12.5,
+                cpu_time: 1_000_000,
+                memory_current: 64 << 20,
+                memory_max: Some(256 << 20),
+                io_read_bytes: 4096,
+                io_written_bytes: 8192,
+                pids: 5,
+            }],
+            limits: Some(Limits { cpus: Some(1.5), memory: Some(2 << 30) }),
+            usage_scope: UsageScope::Limits,
+        }
+    }
+
+    #[test]
+    fn resources_round_trip() {
+        let resources = filled_in();
+        let json = serde_json::to_string(&resources).unwrap();
+        assert_eq!(serde_json::from_str::<Resources>(&json).unwrap(), resources);
+    }
+
+    /// A payload of an agent from before the schema was versioned, with none of the fields added since
+    #[test]
+    fn resources_from_unversioned_agent() {
+        let json = r#"{
+            "hostname": "old",
+            "total_memory": 1024, "used_memory": 512,
+            "total_space": 2048, "available_space": 1024,
+            "used_swap": 0, "total_swap": 0,
+            "cpu_amount": 2, "cpu_usage": 25.0, "cpu_load_per_core": [20.0, 30.0],
+            "disk_names": ["sda1"], "disk_available": [1024], "disk_total": [2048],
+            "uptime": 60, "os_version": "Linux", "kernel_version": "5.4.0",
+            "load_avg_one": 0.5, "load_avg_five": 0.25, "load_avg_fifteen": 0.1,
+            "bytes_received": 10, "bytes_transmitted": 20
+        }"#;
+        let resources: Resources = serde_json::from_str(json).unwrap();
+        assert_eq!(resources.schema_version, 0);
+        assert_eq!(resources.hostname, "old");
+        assert_eq!(resources.disk_names, ["sda1"]);
+        assert!(resources.disk_mount_points.is_empty());
+        assert!(resources.disk_file_systems.is_empty());
+        assert!(resources.networks.is_empty());
+        assert!(resources.sensors.is_empty());
+        assert!(resources.disk_io.is_empty());
+        assert!(resources.cgroups.is_empty());
+        assert_eq!(resources.memory, None);
+        assert_eq!(resources.pressure, None);
+        assert_eq!(resources.limits, None);
+        assert_eq!(resources.usage_scope, UsageScope::Host);
+        assert!(!resources.from_newer_agent());
+    }
+
+    #[test]
+    fn newer_agents_are_recognised() {
+        let current = Resources { schema_version: SCHEMA_VERSION, ..Default::default() };
+        let newer = Resources { schema_version: SCHEMA_VERSION + 1, ..Default::default() };
+        assert!(!current.from_newer_agent());
+        assert!(newer.from_newer_agent());
+    }
+
+    #[test]
+    fn history_samples_default_left_out_fields() {
+        let sample: HistorySample = serde_json::from_str(r#"{"timestamp": 1700000000000, "cpu_usage": 12.5}"#).unwrap();
+        assert_eq!(sample, HistorySample { timestamp: 1_700_000_000_000, cpu_usage: 12.5, ..Default::default() });
+    }
+}